@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE rules DROP COLUMN attribute_filters;
+ALTER TABLE parsed_titles DROP COLUMN attributes;
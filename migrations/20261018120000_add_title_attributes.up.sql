@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE parsed_titles ADD COLUMN attributes TEXT;
+ALTER TABLE rules ADD COLUMN attribute_filters TEXT;
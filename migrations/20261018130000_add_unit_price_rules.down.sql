@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE rules DROP COLUMN price_per_gb_max;
+ALTER TABLE rules DROP COLUMN price_per_tb_max;
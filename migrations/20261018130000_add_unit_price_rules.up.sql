@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE rules ADD COLUMN price_per_tb_max REAL;
+ALTER TABLE rules ADD COLUMN price_per_gb_max REAL;
@@ -0,0 +1,9 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS parse_failures (
+    post_id TEXT NOT NULL REFERENCES posts (id),
+    parser TEXT NOT NULL,
+    parser_version INTEGER NOT NULL,
+    reason TEXT NOT NULL,
+    created_utc TEXT NOT NULL,
+    PRIMARY KEY (post_id, parser, parser_version)
+);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS parsed_titles_post_offer;
+ALTER TABLE rule_matches DROP COLUMN offer_index;
+ALTER TABLE parsed_titles DROP COLUMN offer_index;
@@ -0,0 +1,8 @@
+-- Add up migration script here
+ALTER TABLE parsed_titles ADD COLUMN offer_index INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE rule_matches ADD COLUMN offer_index INTEGER NOT NULL DEFAULT 0;
+
+-- One row per offer, drop any duplicates written before this was enforced
+DELETE FROM parsed_titles
+WHERE rowid NOT IN (SELECT MIN(rowid) FROM parsed_titles GROUP BY post_id, offer_index);
+CREATE UNIQUE INDEX IF NOT EXISTS parsed_titles_post_offer ON parsed_titles (post_id, offer_index);
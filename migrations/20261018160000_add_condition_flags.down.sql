@@ -0,0 +1,8 @@
+-- Add down migration script here
+ALTER TABLE rules DROP COLUMN condition_filters;
+ALTER TABLE parsed_titles DROP COLUMN backorder;
+ALTER TABLE parsed_titles DROP COLUMN preorder;
+ALTER TABLE parsed_titles DROP COLUMN b_stock;
+ALTER TABLE parsed_titles DROP COLUMN used;
+ALTER TABLE parsed_titles DROP COLUMN open_box;
+ALTER TABLE parsed_titles DROP COLUMN refurbished;
@@ -0,0 +1,8 @@
+-- Add up migration script here
+ALTER TABLE parsed_titles ADD COLUMN refurbished BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE parsed_titles ADD COLUMN open_box BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE parsed_titles ADD COLUMN used BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE parsed_titles ADD COLUMN b_stock BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE parsed_titles ADD COLUMN preorder BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE parsed_titles ADD COLUMN backorder BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE rules ADD COLUMN condition_filters TEXT;
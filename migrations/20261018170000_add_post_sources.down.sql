@@ -0,0 +1,5 @@
+-- Add down migration script here
+ALTER TABLE rules DROP COLUMN subreddits;
+ALTER TABLE parsed_titles DROP COLUMN currency;
+ALTER TABLE posts DROP COLUMN permalink;
+ALTER TABLE posts DROP COLUMN subreddit;
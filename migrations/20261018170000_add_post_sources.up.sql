@@ -0,0 +1,8 @@
+-- Add up migration script here
+ALTER TABLE posts ADD COLUMN subreddit TEXT;
+ALTER TABLE posts ADD COLUMN permalink TEXT;
+ALTER TABLE parsed_titles ADD COLUMN currency TEXT NOT NULL DEFAULT 'USD';
+ALTER TABLE rules ADD COLUMN subreddits TEXT;
+
+-- Everything stored so far came from r/buildapcsales
+UPDATE posts SET subreddit = 'buildapcsales' WHERE subreddit IS NULL;
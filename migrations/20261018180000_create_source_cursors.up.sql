@@ -0,0 +1,6 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS source_cursors (
+    source TEXT PRIMARY KEY NOT NULL,
+    newest_fullname TEXT NOT NULL,
+    updated_utc TEXT NOT NULL
+);
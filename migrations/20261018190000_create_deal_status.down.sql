@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE notifications;
+DROP INDEX post_statuses_post_id;
+DROP TABLE post_statuses;
@@ -0,0 +1,18 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS post_statuses (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    post_id TEXT NOT NULL REFERENCES posts (id),
+    status TEXT NOT NULL,
+    link_flair_text TEXT,
+    checked_utc TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS post_statuses_post_id ON post_statuses (post_id);
+
+CREATE TABLE IF NOT EXISTS notifications (
+    post_id TEXT NOT NULL REFERENCES posts (id),
+    channel_id TEXT NOT NULL,
+    message_id TEXT NOT NULL,
+    created_utc TEXT NOT NULL,
+    PRIMARY KEY (post_id, message_id)
+);
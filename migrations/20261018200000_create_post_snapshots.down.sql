@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE rules DROP COLUMN velocity_filters;
+DROP TABLE post_snapshots;
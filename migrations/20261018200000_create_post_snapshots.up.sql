@@ -0,0 +1,11 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS post_snapshots (
+    post_id TEXT NOT NULL REFERENCES posts (id),
+    taken_utc REAL NOT NULL,
+    score INTEGER NOT NULL,
+    upvote_ratio REAL NOT NULL,
+    num_comments INTEGER NOT NULL,
+    PRIMARY KEY (post_id, taken_utc)
+);
+
+ALTER TABLE rules ADD COLUMN velocity_filters TEXT;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX comment_matches_post_id;
+DROP TABLE comment_matches;
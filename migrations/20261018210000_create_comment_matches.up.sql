@@ -0,0 +1,12 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS comment_matches (
+    comment_id TEXT PRIMARY KEY,
+    post_id TEXT NOT NULL REFERENCES posts (id),
+    comment_rule TEXT NOT NULL,
+    author TEXT,
+    body TEXT NOT NULL,
+    score INTEGER NOT NULL,
+    matched_utc TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS comment_matches_post_id ON comment_matches (post_id);
@@ -0,0 +1,16 @@
+-- Add down migration script here
+ALTER TABLE rules DROP COLUMN stickied;
+ALTER TABLE rules DROP COLUMN over_18;
+ALTER TABLE rules DROP COLUMN is_self;
+ALTER TABLE rules DROP COLUMN author_pattern;
+ALTER TABLE rules DROP COLUMN domain_pattern;
+ALTER TABLE rules DROP COLUMN body_pattern;
+
+ALTER TABLE posts DROP COLUMN thumbnail;
+ALTER TABLE posts DROP COLUMN num_comments;
+ALTER TABLE posts DROP COLUMN stickied;
+ALTER TABLE posts DROP COLUMN over_18;
+ALTER TABLE posts DROP COLUMN is_self;
+ALTER TABLE posts DROP COLUMN domain;
+ALTER TABLE posts DROP COLUMN author;
+ALTER TABLE posts DROP COLUMN selftext;
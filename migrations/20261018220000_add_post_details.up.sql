@@ -0,0 +1,16 @@
+-- Add up migration script here
+ALTER TABLE posts ADD COLUMN selftext TEXT;
+ALTER TABLE posts ADD COLUMN author TEXT;
+ALTER TABLE posts ADD COLUMN domain TEXT;
+ALTER TABLE posts ADD COLUMN is_self INTEGER;
+ALTER TABLE posts ADD COLUMN over_18 INTEGER;
+ALTER TABLE posts ADD COLUMN stickied INTEGER;
+ALTER TABLE posts ADD COLUMN num_comments INTEGER;
+ALTER TABLE posts ADD COLUMN thumbnail TEXT;
+
+ALTER TABLE rules ADD COLUMN body_pattern TEXT;
+ALTER TABLE rules ADD COLUMN domain_pattern TEXT;
+ALTER TABLE rules ADD COLUMN author_pattern TEXT;
+ALTER TABLE rules ADD COLUMN is_self INTEGER;
+ALTER TABLE rules ADD COLUMN over_18 INTEGER;
+ALTER TABLE rules ADD COLUMN stickied INTEGER;
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE posts ADD COLUMN source_kind TEXT NOT NULL DEFAULT 'reddit';
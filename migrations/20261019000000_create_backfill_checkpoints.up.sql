@@ -0,0 +1,8 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS backfill_checkpoints (
+    name TEXT PRIMARY KEY NOT NULL,
+    position TEXT,
+    since_utc REAL NOT NULL,
+    finished INTEGER NOT NULL DEFAULT 0,
+    updated_utc TEXT NOT NULL
+);
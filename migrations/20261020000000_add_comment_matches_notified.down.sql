@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE comment_matches DROP COLUMN notified_utc;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+ALTER TABLE comment_matches ADD COLUMN notified_utc TEXT;
+
+-- Matches from before this were all sent, or failed to send, already
+UPDATE comment_matches SET notified_utc = matched_utc;
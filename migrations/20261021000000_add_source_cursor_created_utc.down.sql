@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE source_cursors DROP COLUMN newest_created_utc;
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE source_cursors ADD COLUMN newest_created_utc REAL;
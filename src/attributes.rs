@@ -0,0 +1,559 @@
+use std::{fmt::{self, Display}, str::FromStr, sync::LazyLock};
+
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, de, de::Visitor};
+use thiserror::Error;
+
+/// Structured attributes pulled out of a title's description. Which fields get
+/// filled in depends on the product type, everything else stays `None`.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone)]
+#[serde(default)]
+pub struct Attributes {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capacity_gb: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interface: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kit_size: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed_mhz: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cas_latency: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vram_gb: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_inches: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_hz: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wattage: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub efficiency: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Category {
+    Storage,
+    Memory,
+    Gpu,
+    Monitor,
+    Psu,
+}
+
+impl Category {
+    pub fn from_product_type(product_type: &str) -> Option<Self> {
+        let product_type = product_type.to_uppercase();
+        let has = |s: &str| product_type.contains(s);
+
+        if has("SSD") || has("NVME") || has("M.2") || has("HDD") {
+            Some(Self::Storage)
+        } else if has("RAM") || has("MEMORY") || has("DDR") {
+            Some(Self::Memory)
+        } else if has("GPU") || has("VIDEO CARD") || has("GRAPHICS") {
+            Some(Self::Gpu)
+        } else if has("MONITOR") {
+            Some(Self::Monitor)
+        } else if has("PSU") || has("POWER SUPPLY") {
+            Some(Self::Psu)
+        } else {
+            None
+        }
+    }
+}
+
+static STORAGE_CAPACITY: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)(\d+(?:\.\d+)?)\s?(TB|GB)\b").unwrap());
+static MEMORY_KIT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\b(\d)\s?x\s?(\d+)\s?GB\b").unwrap());
+static MEMORY_CAPACITY: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\b(\d+)\s?GB\b").unwrap());
+static MEMORY_SPEED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)(?:DDR\d[- ]?(\d{4})\b|\b(\d{4})\s?(?:MHz|MT/s))").unwrap());
+static CAS_LATENCY: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\b(?:CL|C|CAS\s?)(\d{2})\b").unwrap());
+static GPU_CHIP: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(RTX|GTX|RX|Arc)\s?([A-Z]?\d{3,4})\b(?:\s?(Ti Super|Ti|Super|XTX|XT|GRE)\b)?").unwrap()
+});
+static VRAM: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\b(\d{1,2})\s?GB\b").unwrap());
+static MONITOR_SIZE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(?i)\b(\d{2}(?:\.\d)?)\s?(?:"|''|in\b|inch)"#).unwrap());
+static RESOLUTION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b(\d{3,4})\s?x\s?(\d{3,4})\b").unwrap());
+static REFRESH_RATE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\b(\d{2,3})\s?Hz\b").unwrap());
+static PSU_WATTAGE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\b(\d{3,4})\s?W\b").unwrap());
+static PSU_EFFICIENCY: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\b(Titanium|Platinum|Gold|Silver|Bronze)\b").unwrap());
+
+/// Resolutions named by keyword, highest first.
+static RESOLUTION_KEYWORDS: LazyLock<[(Regex, &str); 4]> = LazyLock::new(|| {
+    [
+        (Regex::new(r"(?i)\b(?:4k|uhd|2160p)\b").unwrap(), "3840x2160"),
+        (Regex::new(r"(?i)\buwqhd\b").unwrap(), "3440x1440"),
+        (Regex::new(r"(?i)\b(?:qhd|1440p|wqhd)\b").unwrap(), "2560x1440"),
+        (Regex::new(r"(?i)\b(?:fhd|1080p)\b").unwrap(), "1920x1080"),
+    ]
+});
+
+impl Attributes {
+    pub fn extract(product_type: &str, description: &str) -> Self {
+        match Category::from_product_type(product_type) {
+            Some(Category::Storage) => Self::extract_storage(description),
+            Some(Category::Memory) => Self::extract_memory(description),
+            Some(Category::Gpu) => Self::extract_gpu(description),
+            Some(Category::Monitor) => Self::extract_monitor(description),
+            Some(Category::Psu) => Self::extract_psu(description),
+            None => Self::default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn capacity_tb(&self) -> Option<f64> {
+        self.capacity_gb.map(|gb| gb / 1000.0)
+    }
+
+    fn extract_storage(description: &str) -> Self {
+        // Take the largest size mentioned so a DRAM cache size doesn't win
+        let capacity_gb = STORAGE_CAPACITY
+            .captures_iter(description)
+            .filter_map(|c| {
+                let amount: f64 = c[1].parse().ok()?;
+                match c[2].to_uppercase().as_str() {
+                    "TB" => Some(amount * 1000.0),
+                    _ => Some(amount),
+                }
+            })
+            .reduce(f64::max);
+
+        let lower = description.to_lowercase();
+        let interface = if lower.contains("nvme") || lower.contains("pcie") {
+            Some("NVMe".to_owned())
+        } else if lower.contains("sata") {
+            Some("SATA".to_owned())
+        } else {
+            None
+        };
+
+        Self {
+            capacity_gb,
+            interface,
+            ..Self::default()
+        }
+    }
+
+    fn extract_memory(description: &str) -> Self {
+        let mut attributes = Self::default();
+
+        if let Some(c) = MEMORY_KIT.captures(description) {
+            let sticks: u32 = c[1].parse().unwrap_or(1);
+            let per_stick: f64 = c[2].parse().unwrap_or(0.0);
+            attributes.kit_size = Some(sticks);
+            attributes.capacity_gb = Some(f64::from(sticks) * per_stick);
+        } else {
+            attributes.capacity_gb = MEMORY_CAPACITY
+                .captures(description)
+                .and_then(|c| c[1].parse().ok());
+        }
+
+        attributes.speed_mhz = MEMORY_SPEED.captures(description).and_then(|c| {
+            c.get(1).or_else(|| c.get(2))?.as_str().parse().ok()
+        });
+
+        attributes.cas_latency = CAS_LATENCY.captures(description).and_then(|c| c[1].parse().ok());
+
+        attributes
+    }
+
+    fn extract_gpu(description: &str) -> Self {
+        let chip = GPU_CHIP.captures(description).map(|c| {
+            let mut chip = format!("{} {}", normalize_gpu_brand(&c[1]), c[2].to_uppercase());
+            if let Some(suffix) = c.get(3) {
+                chip.push(' ');
+                chip.push_str(&normalize_gpu_suffix(suffix.as_str()));
+            }
+            chip
+        });
+
+        let vram_gb = VRAM.captures(description).and_then(|c| c[1].parse().ok());
+
+        Self {
+            chip,
+            vram_gb,
+            ..Self::default()
+        }
+    }
+
+    fn extract_monitor(description: &str) -> Self {
+        let size_inches = MONITOR_SIZE.captures(description).and_then(|c| c[1].parse().ok());
+
+        let resolution = RESOLUTION
+            .captures(description)
+            .map(|c| format!("{}x{}", &c[1], &c[2]))
+            .or_else(|| resolution_from_keyword(description));
+
+        let refresh_hz = REFRESH_RATE.captures(description).and_then(|c| c[1].parse().ok());
+
+        Self {
+            size_inches,
+            resolution,
+            refresh_hz,
+            ..Self::default()
+        }
+    }
+
+    fn extract_psu(description: &str) -> Self {
+        let wattage = PSU_WATTAGE.captures(description).and_then(|c| c[1].parse().ok());
+
+        let efficiency = PSU_EFFICIENCY.captures(description).map(|c| capitalize(&c[1]));
+
+        Self {
+            wattage,
+            efficiency,
+            ..Self::default()
+        }
+    }
+
+    fn get(&self, attribute: Attribute) -> Option<AttributeValue> {
+        let number = |n: Option<f64>| n.map(AttributeValue::Number);
+        let text = |s: &Option<String>| s.clone().map(AttributeValue::Text);
+
+        match attribute {
+            Attribute::CapacityGb => number(self.capacity_gb),
+            Attribute::CapacityTb => number(self.capacity_tb()),
+            Attribute::Interface => text(&self.interface),
+            Attribute::KitSize => number(self.kit_size.map(f64::from)),
+            Attribute::Speed => number(self.speed_mhz.map(f64::from)),
+            Attribute::Cas => number(self.cas_latency.map(f64::from)),
+            Attribute::Chip => text(&self.chip),
+            Attribute::Vram => number(self.vram_gb.map(f64::from)),
+            Attribute::Size => number(self.size_inches),
+            Attribute::Resolution => text(&self.resolution),
+            Attribute::Refresh => number(self.refresh_hz.map(f64::from)),
+            Attribute::Wattage => number(self.wattage.map(f64::from)),
+            Attribute::Efficiency => text(&self.efficiency),
+        }
+    }
+}
+
+fn normalize_gpu_brand(brand: &str) -> String {
+    match brand.to_uppercase().as_str() {
+        "ARC" => "Arc".to_owned(),
+        other => other.to_owned(),
+    }
+}
+
+fn normalize_gpu_suffix(suffix: &str) -> String {
+    match suffix.to_uppercase().as_str() {
+        "TI" => "Ti".to_owned(),
+        "SUPER" => "Super".to_owned(),
+        "TI SUPER" => "Ti Super".to_owned(),
+        other => other.to_owned(),
+    }
+}
+
+fn resolution_from_keyword(description: &str) -> Option<String> {
+    RESOLUTION_KEYWORDS
+        .iter()
+        .find(|(keyword, _)| keyword.is_match(description))
+        .map(|(_, resolution)| (*resolution).to_owned())
+}
+
+fn capitalize(s: &str) -> String {
+    let lower = s.to_lowercase();
+    let mut chars = lower.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Attribute {
+    CapacityGb,
+    CapacityTb,
+    Interface,
+    KitSize,
+    Speed,
+    Cas,
+    Chip,
+    Vram,
+    Size,
+    Resolution,
+    Refresh,
+    Wattage,
+    Efficiency,
+}
+
+impl FromStr for Attribute {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "capacity_gb" => Ok(Self::CapacityGb),
+            "capacity_tb" => Ok(Self::CapacityTb),
+            "interface" => Ok(Self::Interface),
+            "kit_size" => Ok(Self::KitSize),
+            "speed" | "speed_mhz" => Ok(Self::Speed),
+            "cas" | "cas_latency" => Ok(Self::Cas),
+            "chip" => Ok(Self::Chip),
+            "vram" | "vram_gb" => Ok(Self::Vram),
+            "size" | "size_inches" => Ok(Self::Size),
+            "resolution" => Ok(Self::Resolution),
+            "refresh" | "refresh_hz" => Ok(Self::Refresh),
+            "wattage" => Ok(Self::Wattage),
+            "efficiency" => Ok(Self::Efficiency),
+            _ => Err(Error::UnknownAttribute(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Comparison {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl FromStr for Comparison {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "<" => Ok(Self::Lt),
+            "<=" => Ok(Self::Le),
+            ">" => Ok(Self::Gt),
+            ">=" => Ok(Self::Ge),
+            "=" | "==" => Ok(Self::Eq),
+            "!=" => Ok(Self::Ne),
+            _ => Err(Error::UnknownComparison(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum AttributeValue {
+    Number(f64),
+    Text(String),
+}
+
+impl Display for AttributeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(n) => n.fmt(f),
+            Self::Text(s) => f.write_fmt(format_args!("\"{s}\"")),
+        }
+    }
+}
+
+impl Comparison {
+    pub fn compare(self, actual: f64, expected: f64) -> bool {
+        match self {
+            Self::Lt => actual < expected,
+            Self::Le => actual <= expected,
+            Self::Gt => actual > expected,
+            Self::Ge => actual >= expected,
+            Self::Eq => (actual - expected).abs() < f64::EPSILON,
+            Self::Ne => (actual - expected).abs() >= f64::EPSILON,
+        }
+    }
+}
+
+impl AttributeValue {
+    fn compare(&self, op: Comparison, expected: &Self) -> bool {
+        match (self, expected) {
+            (Self::Number(actual), Self::Number(expected)) => op.compare(*actual, *expected),
+            (Self::Text(actual), Self::Text(expected)) => match op {
+                Comparison::Eq => actual.eq_ignore_ascii_case(expected),
+                Comparison::Ne => !actual.eq_ignore_ascii_case(expected),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Error)]
+pub enum Error {
+    #[error("unknown attribute {0}")]
+    UnknownAttribute(String),
+    #[error("unknown comparison {0}")]
+    UnknownComparison(String),
+    #[error("expected `<attribute> <comparison> <value>`, got: {0}")]
+    Malformed(String),
+}
+
+/// A single `<attribute> <comparison> <value>` filter, e.g. `capacity_tb >= 2`.
+/// A title without the attribute never matches the filter.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AttributeFilter {
+    pub source: String,
+    pub attribute: Attribute,
+    pub comparison: Comparison,
+    pub value: AttributeValue,
+}
+
+impl AttributeFilter {
+    pub fn is_match(&self, attributes: &Attributes) -> bool {
+        attributes
+            .get(self.attribute)
+            .is_some_and(|actual| actual.compare(self.comparison, &self.value))
+    }
+}
+
+static FILTER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*([a-z_]+)\s*(<=|>=|==|!=|<|>|=)\s*(.+?)\s*$").unwrap());
+
+impl FromStr for AttributeFilter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(c) = FILTER.captures(s) else {
+            return Err(Error::Malformed(s.to_owned()));
+        };
+
+        let attribute = c[1].parse()?;
+        let comparison = c[2].parse()?;
+        let value = match c[3].parse() {
+            Ok(n) => AttributeValue::Number(n),
+            _ => AttributeValue::Text(c[3].trim_matches('"').to_owned()),
+        };
+
+        Ok(Self {
+            source: s.to_owned(),
+            attribute,
+            comparison,
+            value,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for AttributeFilter {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct AttributeFilterVisitor;
+        impl<'de> Visitor<'de> for AttributeFilterVisitor {
+            type Value = AttributeFilter;
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+                where
+                    E: de::Error, {
+                v.parse()
+                    .map_err(|e| de::Error::custom(format!("failed to parse attribute filter: {e}")))
+            }
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("expected a string")
+            }
+        }
+
+        deserializer.deserialize_str(AttributeFilterVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.unwrap();
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn test_extract_ssd() {
+        let attributes = Attributes::extract(
+            "SSD",
+            "WD_BLACK SN850X 2TB NVMe PCIe Gen4 M.2 2280 Internal SSD",
+        );
+
+        assert_close(attributes.capacity_gb, 2000.0);
+        assert_close(attributes.capacity_tb(), 2.0);
+        assert_eq!(attributes.interface, Some("NVMe".to_owned()));
+    }
+
+    #[test]
+    fn test_extract_ram() {
+        let attributes = Attributes::extract(
+            "RAM",
+            "G.Skill Flare X5 32GB (2x16GB) DDR5-6000 CL30 AMD EXPO",
+        );
+
+        assert_close(attributes.capacity_gb, 32.0);
+        assert_eq!(Attributes { capacity_gb: None, ..attributes }, Attributes {
+            kit_size: Some(2),
+            speed_mhz: Some(6000),
+            cas_latency: Some(30),
+            ..Attributes::default()
+        });
+    }
+
+    #[test]
+    fn test_extract_gpu() {
+        let attributes = Attributes::extract(
+            "GPU",
+            "ASUS - NVIDIA GeForce RTX 4070 Ti TUF 12GB GDDR6X",
+        );
+
+        assert_eq!(attributes.chip, Some("RTX 4070 Ti".to_owned()));
+        assert_eq!(attributes.vram_gb, Some(12));
+    }
+
+    #[test]
+    fn test_extract_monitor() {
+        let attributes = Attributes::extract(
+            "Monitor",
+            "LG 27GP850-B 27\" 1440p 165Hz Nano IPS Gaming Monitor",
+        );
+
+        assert_close(attributes.size_inches, 27.0);
+        assert_eq!(Attributes { size_inches: None, ..attributes }, Attributes {
+            resolution: Some("2560x1440".to_owned()),
+            refresh_hz: Some(165),
+            ..Attributes::default()
+        });
+    }
+
+    #[test]
+    fn test_extract_psu() {
+        let attributes = Attributes::extract("PSU", "Corsair RM850x 850W 80+ Gold Fully Modular");
+
+        assert_eq!(attributes.wattage, Some(850));
+        assert_eq!(attributes.efficiency, Some("Gold".to_owned()));
+    }
+
+    #[test]
+    fn test_filter_parse_and_match() {
+        let filter: AttributeFilter = "capacity_tb >= 2".parse().unwrap();
+        assert_eq!(filter.attribute, Attribute::CapacityTb);
+        assert_eq!(filter.comparison, Comparison::Ge);
+        let AttributeValue::Number(value) = &filter.value else { panic!("not a number: {:?}", filter.value) };
+        assert_close(Some(*value), 2.0);
+
+        let two_tb = Attributes { capacity_gb: Some(2000.0), ..Attributes::default() };
+        let one_tb = Attributes { capacity_gb: Some(1000.0), ..Attributes::default() };
+        assert!(filter.is_match(&two_tb));
+        assert!(!filter.is_match(&one_tb));
+        assert!(!filter.is_match(&Attributes::default()));
+    }
+
+    #[test]
+    fn test_filter_text() {
+        let filter: AttributeFilter = "interface == nvme".parse().unwrap();
+        let nvme = Attributes { interface: Some("NVMe".to_owned()), ..Attributes::default() };
+        assert!(filter.is_match(&nvme));
+    }
+
+    #[test]
+    fn test_filter_malformed() {
+        assert_eq!(
+            "refresh".parse::<AttributeFilter>(),
+            Err(Error::Malformed("refresh".to_owned()))
+        );
+        assert_eq!(
+            "color == red".parse::<AttributeFilter>(),
+            Err(Error::UnknownAttribute("color".to_owned()))
+        );
+    }
+}
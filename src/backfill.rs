@@ -0,0 +1,398 @@
+use std::path::PathBuf;
+
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::{config, db, error::Error, http, models::Post, parser, poll, reddit::{self, ListingRequest}, retry::{self, Backoff}, rule::Rules, source::Sources};
+
+/// Dump checkpoints are saved every this many lines.
+const FILE_CHECKPOINT_LINES: u64 = 1000;
+
+/// Where history comes from.
+#[derive(Debug)]
+pub enum Target {
+    /// Paged back through `/r/{subreddit}/new`
+    Subreddit(String),
+    /// A JSON lines dump with one post per line, like Pushshift's, optionally
+    /// filtered to one subreddit
+    File { path: PathBuf, subreddit: Option<String> },
+}
+
+impl Target {
+    /// What the checkpoint is saved under.
+    fn name(&self) -> String {
+        match self {
+            Self::Subreddit(subreddit) => format!("r/{subreddit}"),
+            Self::File { path, subreddit: None } => format!("file:{}", path.display()),
+            Self::File { path, subreddit: Some(subreddit) } => format!("file:{}#r/{subreddit}", path.display()),
+        }
+    }
+}
+
+/// How far a backfill got, saved after every page so it can be resumed.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct Checkpoint {
+    pub name: String,
+    /// Fullname of the oldest post stored from a listing, or the number of
+    /// lines of a dump already read
+    pub position: Option<String>,
+    pub since_utc: f64,
+    pub finished: bool,
+}
+
+/// Stores posts created at or after `since` along with their parsed titles
+/// and rule matches, without sending notifications. An unfinished backfill of
+/// the same target back to the same date carries on from its checkpoint
+/// unless `restart` is set.
+///
+/// The poller skips posts that are already stored, so any it hasn't seen yet
+/// when the backfill stores them are never notified. Backfilling is meant
+/// for history, and is best run while polling is stopped.
+pub async fn backfill(config: config::Config, target: Target, since: f64, restart: bool) -> Result<(), Error> {
+    let parsers = parser::Registry::from_config(&config.parser_configs())?;
+
+    let mut db = db::Client::new(config.db);
+    db.connect().await?;
+
+    poll::write_rules(&db, &config.rules).await?;
+
+    let name = target.name();
+    let checkpoint = match db.get_backfill_checkpoint(&name).await? {
+        Some(checkpoint) if !restart && checkpoint.since_utc == since => {
+            if checkpoint.finished {
+                log::info!("{name} has already been backfilled to that date, pass --restart to run it again");
+                return Ok(());
+            }
+            log::info!("Resuming {name} from {:?}", checkpoint.position);
+            checkpoint
+        }
+        _ => Checkpoint {
+            name,
+            position: None,
+            since_utc: since,
+            finished: false,
+        },
+    };
+
+    let mut store = Store {
+        db,
+        rules: config.rules,
+        parsers,
+        sources: config.sources,
+        posts: 0,
+        new_posts: 0,
+        matches: 0,
+    };
+    match target {
+        Target::Subreddit(subreddit) => {
+            let http = http::ReqwestClient::shared(&config.http)?;
+            let page_size = config.reddit.page_size;
+            let mut client = reddit::Client::new(config.reddit, http);
+            client.read_auth_from_file().await?;
+            store.subreddit(&mut client, &subreddit, page_size, config.retry, checkpoint).await?;
+        }
+        Target::File { path, subreddit } => {
+            store.file(&path, subreddit.as_deref(), checkpoint).await?;
+        }
+    }
+
+    log::info!("Backfill done: read {} posts, {} were new, {} matched a rule", store.posts, store.new_posts, store.matches);
+    Ok(())
+}
+
+struct Store {
+    db: db::Client,
+    rules: Rules,
+    parsers: parser::Registry,
+    sources: Sources,
+    posts: u64,
+    new_posts: u64,
+    matches: u64,
+}
+
+impl Store {
+    /// Stores a post like the polling loop does, skipping ones already stored.
+    async fn store(&mut self, post: Post) -> Result<(), Error> {
+        self.posts += 1;
+        if !self.db.insert_post(&post).await? {
+            return Ok(());
+        }
+
+        self.new_posts += 1;
+        let matches = poll::process_post(&self.db, post, &self.rules, &self.parsers, &self.sources).await?;
+        self.matches += matches.len() as u64;
+        Ok(())
+    }
+
+    /// Pages back through the listing until a post older than the checkpoint's
+    /// date, waiting out the rate limit and retrying failed pages.
+    async fn subreddit(&mut self, client: &mut reddit::Client, subreddit: &str, page_size: u64, retry: retry::Config, mut checkpoint: Checkpoint) -> Result<(), Error> {
+        let mut backoff = Backoff::new(retry);
+        while !checkpoint.finished {
+            if let Some(reset) = client.get_ratelimit_wait() {
+                log::info!("Out of requests, waiting {}s for the rate limit to reset", reset.as_secs());
+                tokio::time::sleep(reset).await;
+            }
+
+            let request = ListingRequest {
+                limit: page_size,
+                after: checkpoint.position.clone(),
+            };
+            let listing = match fetch_page(client, subreddit, &request).await {
+                Ok(listing) => listing,
+                Err(e) if e.is_retryable() => {
+                    let wait = backoff.failed(&e);
+                    log::warn!("Fetching r/{subreddit} failed ({} in a row), retrying in {}s: {e}", backoff.failures(), wait.as_secs());
+                    tokio::time::sleep(wait).await;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            backoff.succeeded();
+
+            // Carrying on after the last stored post, rather than the page's
+            // `after`, means a page cut short by the date isn't skipped when
+            // the checkpoint is reused
+            let mut reached_since = false;
+            for child in listing.data.children {
+                let mut post = child.data;
+                if post.created_utc < checkpoint.since_utc {
+                    reached_since = true;
+                    break;
+                }
+                if post.subreddit.is_empty() {
+                    subreddit.clone_into(&mut post.subreddit);
+                }
+                checkpoint.position = Some(post.fullname());
+                self.store(post).await?;
+            }
+
+            checkpoint.finished = reached_since || listing.data.after.is_none();
+            if checkpoint.finished && !reached_since {
+                log::warn!("Reached the end of r/{subreddit}'s listing, which only goes back about 1000 posts. Use a dump file for older posts.");
+            }
+            self.db.set_backfill_checkpoint(&checkpoint).await?;
+            log::info!("Stored {} posts from r/{subreddit} so far", self.posts);
+        }
+
+        Ok(())
+    }
+
+    /// Reads every line of a dump after the checkpoint. Dumps aren't always
+    /// in order, so older posts are skipped rather than ending the read.
+    async fn file(&mut self, path: &std::path::Path, subreddit: Option<&str>, mut checkpoint: Checkpoint) -> Result<(), Error> {
+        let skip: u64 = checkpoint.position.as_deref().map(str::parse).transpose()?.unwrap_or(0);
+        let mut lines = BufReader::new(tokio::fs::File::open(path).await?).lines();
+
+        let mut line_number = 0;
+        while let Some(line) = lines.next_line().await? {
+            line_number += 1;
+            if line_number <= skip {
+                continue;
+            }
+
+            match parse_dump_line(&line) {
+                Ok(Some(post)) => {
+                    let in_subreddit = subreddit.is_none_or(|subreddit| post.subreddit.eq_ignore_ascii_case(subreddit));
+                    if in_subreddit && post.created_utc >= checkpoint.since_utc {
+                        self.store(post).await?;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => log::warn!("Skipping line {line_number} of {}: {e}", path.display()),
+            }
+
+            if line_number % FILE_CHECKPOINT_LINES == 0 {
+                checkpoint.position = Some(line_number.to_string());
+                self.db.set_backfill_checkpoint(&checkpoint).await?;
+                log::info!("Read {line_number} lines, stored {} posts so far", self.posts);
+            }
+        }
+
+        checkpoint.position = Some(line_number.to_string());
+        checkpoint.finished = true;
+        self.db.set_backfill_checkpoint(&checkpoint).await
+    }
+}
+
+async fn fetch_page(client: &mut reddit::Client, subreddit: &str, request: &ListingRequest) -> Result<reddit::ListingResponse, Error> {
+    client.ensure_authenticated().await?;
+    client.listing_new(&reddit::Listing::Subreddit(subreddit.to_owned()), request).await
+}
+
+/// Parses a dump line holding either a post or a `{"kind": "t3", "data": ...}`
+/// thing. Dumps often leave out fields the API always sends, or give
+/// `created_utc` as a string, so those are filled in first.
+fn parse_dump_line(line: &str) -> Result<Option<Post>, Error> {
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let mut value: Value = serde_json::from_str(line)?;
+    if value.get("kind").and_then(Value::as_str) == Some("t3") {
+        value = value["data"].take();
+    }
+    let Some(post) = value.as_object_mut() else {
+        return Err(Error::Other("not a post".to_owned()));
+    };
+
+    if let Some(created) = post.get("created_utc").and_then(Value::as_str).and_then(|s| s.parse::<f64>().ok()) {
+        post.insert("created_utc".to_owned(), created.into());
+    }
+    let score = post.get("score").cloned().unwrap_or_else(|| 0.into());
+    post.entry("ups").or_insert(score);
+    post.entry("downs").or_insert_with(|| 0.into());
+    post.entry("url").or_insert_with(|| "".into());
+    post.entry("link_flair_text").or_insert(Value::Null);
+
+    Ok(Some(serde_json::from_value(value)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{FakeReddit, TempDir};
+
+    /// Fixture posts are five minutes apart, from 1a0e05 down to 1a0e01. This
+    /// is when 1a0e02 was posted.
+    const SINCE_1A0E02: f64 = 1_760_784_600.0;
+
+    async fn fake_reddit() -> FakeReddit {
+        let fake = FakeReddit::start().await;
+        fake.listing("/r/buildapcsales/new", include_str!("../tests/fixtures/reddit/buildapcsales_new.json"));
+        fake
+    }
+
+    async fn test_config(reddit: &FakeReddit, dir: &TempDir) -> config::Config {
+        let config = config::Config::from_toml(&format!(
+            r#"
+            [[rules]]
+            name = "990 Pro"
+            product_type_pattern = "SSD"
+            description_pattern = "990 Pro"
+
+            [reddit]
+            {reddit}
+            page_size = 2
+
+            [discord]
+            token = ""
+            user_agent = ""
+            api_url = ""
+            channel_id = ""
+            sending_interval_secs = 10
+
+            [twilio]
+            api_url = ""
+            api_key = ""
+            api_key_secret = ""
+            account_sid = ""
+            phone_number_from = ""
+            phone_number_to = ""
+
+            [db]
+            db_url = "{db_url}"
+
+            [retry]
+            initial_backoff_secs = 1
+            max_backoff_secs = 1
+            "#,
+            reddit = reddit.config_toml(&dir.path().join("token.json")),
+            db_url = dir.db_url(),
+        ))
+        .unwrap();
+        db::Client::new(config.db.clone()).setup().await.unwrap();
+        config
+    }
+
+    async fn connect(config: &config::Config) -> db::Client {
+        let mut db = db::Client::new(config.db.clone());
+        db.connect().await.unwrap();
+        db
+    }
+
+    fn subreddit() -> Target {
+        Target::Subreddit("buildapcsales".to_owned())
+    }
+
+    #[tokio::test]
+    async fn test_backfill_subreddit() {
+        let reddit = fake_reddit().await;
+        let dir = TempDir::new("backfill");
+        let config = test_config(&reddit, &dir).await;
+        let db = connect(&config).await;
+
+        backfill(config, subreddit(), SINCE_1A0E02, false).await.unwrap();
+
+        // Pages back until a post older than the date
+        assert_eq!(reddit.requests(), vec![
+            "/r/buildapcsales/new?limit=2",
+            "/r/buildapcsales/new?limit=2&after=t3_1a0e04",
+            "/r/buildapcsales/new?limit=2&after=t3_1a0e02",
+        ]);
+        let mut stored = db.get_recent_post_ids(0.0).await.unwrap();
+        stored.sort();
+        assert_eq!(stored, vec!["1a0e02", "1a0e03", "1a0e04", "1a0e05"]);
+        let checkpoint = db.get_backfill_checkpoint("r/buildapcsales").await.unwrap().unwrap();
+        assert_eq!(checkpoint.position.as_deref(), Some("t3_1a0e02"));
+        assert!(checkpoint.finished);
+
+        // A finished backfill isn't run again
+        backfill(test_config(&reddit, &dir).await, subreddit(), SINCE_1A0E02, false).await.unwrap();
+        assert_eq!(reddit.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_backfill_resumes_from_checkpoint() {
+        let reddit = fake_reddit().await;
+        let dir = TempDir::new("backfill");
+        let config = test_config(&reddit, &dir).await;
+        let db = connect(&config).await;
+
+        // Interrupted after the first page
+        reddit.fail_after(1, 403);
+        assert!(backfill(config, subreddit(), SINCE_1A0E02, false).await.is_err());
+        let checkpoint = db.get_backfill_checkpoint("r/buildapcsales").await.unwrap().unwrap();
+        assert_eq!(checkpoint.position.as_deref(), Some("t3_1a0e04"));
+        assert!(!checkpoint.finished);
+
+        // Carries on from the last stored post
+        backfill(test_config(&reddit, &dir).await, subreddit(), SINCE_1A0E02, false).await.unwrap();
+        assert_eq!(reddit.requests()[2..], [
+            "/r/buildapcsales/new?limit=2&after=t3_1a0e04",
+            "/r/buildapcsales/new?limit=2&after=t3_1a0e02",
+        ]);
+        let mut stored = db.get_recent_post_ids(0.0).await.unwrap();
+        stored.sort();
+        assert_eq!(stored, vec!["1a0e02", "1a0e03", "1a0e04", "1a0e05"]);
+        assert!(db.get_backfill_checkpoint("r/buildapcsales").await.unwrap().unwrap().finished);
+    }
+
+    #[test]
+    fn test_parse_dump_lines() {
+        let post = parse_dump_line(r#"{"id":"abc","title":"[SSD] 990 Pro 2TB $149.99","created_utc":"1697328000","score":12,"subreddit":"buildapcsales","permalink":"/r/buildapcsales/comments/abc/"}"#)
+            .unwrap()
+            .unwrap();
+        assert_eq!(post.id, "abc");
+        assert_eq!(post.created_utc, 1_697_328_000.0);
+        assert_eq!(post.ups, 12.0);
+        assert_eq!(post.url, "");
+
+        let post = parse_dump_line(r#"{"kind":"t3","data":{"id":"def","title":"[GPU] 7800 XT $449","created_utc":1697328100.0,"ups":3,"downs":0,"url":"https://example.com","link_flair_text":"Expired"}}"#)
+            .unwrap()
+            .unwrap();
+        assert_eq!(post.id, "def");
+        assert_eq!(post.link_flair_text.as_deref(), Some("Expired"));
+
+        assert!(parse_dump_line("  ").unwrap().is_none());
+        assert!(parse_dump_line("[1, 2]").is_err());
+        assert!(parse_dump_line(r#"{"id":"ghi"}"#).is_err());
+    }
+
+    #[test]
+    fn test_checkpoint_names() {
+        assert_eq!(Target::Subreddit("buildapcsales".to_owned()).name(), "r/buildapcsales");
+        let file = Target::File { path: PathBuf::from("dumps/bapcs.ndjson"), subreddit: Some("buildapcsales".to_owned()) };
+        assert_eq!(file.name(), "file:dumps/bapcs.ndjson#r/buildapcsales");
+    }
+}
@@ -0,0 +1,214 @@
+use serde::{Deserialize, Deserializer, de::IgnoredAny};
+
+use crate::rule::PatternAndSource;
+
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct Config {
+    /// How often comments on matched posts are scanned
+    #[serde(default = "default_check_interval_secs")]
+    pub check_interval_secs: u64,
+    /// Stop scanning posts older than this
+    #[serde(default = "default_max_age_hours")]
+    pub max_age_hours: u64,
+    /// Newest comments fetched per post
+    #[serde(default = "default_limit")]
+    pub limit: u32,
+}
+
+const fn default_check_interval_secs() -> u64 {
+    900
+}
+
+const fn default_max_age_hours() -> u64 {
+    24
+}
+
+const fn default_limit() -> u32 {
+    100
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            check_interval_secs: default_check_interval_secs(),
+            max_age_hours: default_max_age_hours(),
+            limit: default_limit(),
+        }
+    }
+}
+
+/// A rule run over the comments of matched posts, e.g. to catch coupon codes.
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct CommentRule {
+    pub name: Option<String>,
+    pub body_pattern: PatternAndSource,
+    /// Ignore comments scored below this
+    pub min_score: Option<i64>,
+}
+
+impl CommentRule {
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.body_pattern.source.clone())
+    }
+
+    pub fn is_match(&self, comment: &Comment) -> bool {
+        self.min_score.is_none_or(|min_score| comment.score >= min_score)
+            && self.body_pattern.pattern.does_string_match(&comment.body)
+    }
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Comment {
+    pub id: String,
+    pub author: Option<String>,
+    pub body: String,
+    pub score: i64,
+    pub permalink: String,
+}
+
+impl Comment {
+    pub fn get_url(&self) -> String {
+        format!("https://reddit.com{}", self.permalink)
+    }
+}
+
+/// The first comment rule matching each comment.
+pub fn find_matches<'a>(comments: &'a [Comment], rules: &'a [CommentRule]) -> impl Iterator<Item = (&'a Comment, &'a CommentRule)> {
+    comments
+        .iter()
+        .filter(|comment| comment.author.as_deref() != Some("[deleted]"))
+        .filter_map(|comment| rules.iter().find(|rule| rule.is_match(comment)).map(|rule| (comment, rule)))
+}
+
+/// A page of things in a comment tree, as returned by `/comments/{id}`.
+#[derive(Deserialize, Debug)]
+pub struct Listing {
+    pub data: ListingData,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ListingData {
+    pub children: Vec<Thing>,
+}
+
+/// Comment trees mix comments with "load more" stubs, which are skipped.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "kind", content = "data")]
+pub enum Thing {
+    #[serde(rename = "t1")]
+    Comment(CommentNode),
+    #[serde(rename = "more")]
+    More(IgnoredAny),
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CommentNode {
+    #[serde(flatten)]
+    pub comment: Comment,
+    /// Reddit sends an empty string instead of a listing when there are no
+    /// replies
+    #[serde(default, deserialize_with = "deserialize_replies")]
+    pub replies: Option<Listing>,
+}
+
+fn deserialize_replies<'de, D>(deserializer: D) -> Result<Option<Listing>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Replies {
+        Listing(Listing),
+        Empty(IgnoredAny),
+    }
+
+    Ok(match Replies::deserialize(deserializer)? {
+        Replies::Listing(listing) => Some(listing),
+        Replies::Empty(_) => None,
+    })
+}
+
+impl Listing {
+    /// Every comment in the tree, parents before their replies.
+    pub fn flatten(self) -> Vec<Comment> {
+        let mut comments = Vec::new();
+        let mut stack: Vec<Thing> = self.data.children.into_iter().rev().collect();
+        while let Some(thing) = stack.pop() {
+            if let Thing::Comment(node) = thing {
+                if let Some(replies) = node.replies {
+                    stack.extend(replies.data.children.into_iter().rev());
+                }
+                comments.push(node.comment);
+            }
+        }
+        comments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comment(id: &str, body: &str, replies: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "kind": "t1",
+            "data": {
+                "id": id,
+                "author": "user",
+                "body": body,
+                "score": 3,
+                "permalink": format!("/r/buildapcsales/comments/abc/_/{id}/"),
+                "replies": replies,
+            }
+        })
+    }
+
+    #[test]
+    fn test_flatten_comment_tree() {
+        let listing: Listing = serde_json::from_value(serde_json::json!({
+            "kind": "Listing",
+            "data": {
+                "children": [
+                    comment("c1", "first", serde_json::json!({
+                        "kind": "Listing",
+                        "data": { "children": [
+                            comment("c2", "reply", serde_json::json!("")),
+                            { "kind": "more", "data": { "count": 4, "children": ["c5"] } },
+                        ] }
+                    })),
+                    comment("c3", "second", serde_json::json!("")),
+                ]
+            }
+        })).unwrap();
+
+        let ids: Vec<String> = listing.flatten().into_iter().map(|comment| comment.id).collect();
+        assert_eq!(ids, vec!["c1", "c2", "c3"]);
+    }
+
+    #[test]
+    fn test_find_matches() {
+        let rules: Vec<CommentRule> = toml::from_str::<toml::Value>(r#"
+            [[rules]]
+            name = "Coupon code"
+            body_pattern = "code && (off || discount)"
+
+            [[rules]]
+            body_pattern = "\"price dropped\""
+            min_score = 5
+        "#).unwrap()["rules"].clone().try_into().unwrap();
+
+        let comments: Vec<Comment> = [
+            ("c1", "Use code SAVE15 for 15% off"),
+            ("c2", "Price dropped to $80 at checkout"),
+            ("c3", "No code needed"),
+        ]
+        .into_iter()
+        .map(|(id, body)| serde_json::from_value(comment(id, body, serde_json::json!(""))["data"].clone()).unwrap())
+        .collect();
+
+        let matches: Vec<(&str, String)> = find_matches(&comments, &rules)
+            .map(|(comment, rule)| (comment.id.as_str(), rule.name()))
+            .collect();
+        assert_eq!(matches, vec![("c1", "Coupon code".to_owned())]);
+    }
+}
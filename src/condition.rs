@@ -0,0 +1,165 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+static REFURBISHED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\b(?:refurb(?:ished)?|renewed|recertified)\b").unwrap());
+static OPEN_BOX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\bopen[- ]?box\b").unwrap());
+static USED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\b(?:used|pre-?owned)\b").unwrap());
+static B_STOCK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\bb[- ]?stock\b").unwrap());
+static PREORDER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\bpre-?order(?:ed|s)?\b").unwrap());
+static BACKORDER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\bback-?order(?:ed|s)?\b").unwrap());
+
+/// Condition and listing-type flags detected from a title and its flair.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[serde(default)]
+pub struct Condition {
+    pub refurbished: bool,
+    pub open_box: bool,
+    pub used: bool,
+    pub b_stock: bool,
+    pub preorder: bool,
+    pub backorder: bool,
+}
+
+impl Condition {
+    pub fn detect(text: &str) -> Self {
+        Self {
+            refurbished: REFURBISHED.is_match(text),
+            open_box: OPEN_BOX.is_match(text),
+            used: USED.is_match(text),
+            b_stock: B_STOCK.is_match(text),
+            preorder: PREORDER.is_match(text),
+            backorder: BACKORDER.is_match(text),
+        }
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            refurbished: self.refurbished || other.refurbished,
+            open_box: self.open_box || other.open_box,
+            used: self.used || other.used,
+            b_stock: self.b_stock || other.b_stock,
+            preorder: self.preorder || other.preorder,
+            backorder: self.backorder || other.backorder,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ConditionFilter {
+    /// Don't match listings with the flag
+    Exclude,
+    /// Match listings with or without the flag
+    Allow,
+    /// Only match listings with the flag
+    Only,
+}
+
+impl ConditionFilter {
+    const fn is_match(self, flag: bool) -> bool {
+        match self {
+            Self::Exclude => !flag,
+            Self::Allow => true,
+            Self::Only => flag,
+        }
+    }
+}
+
+/// Per-rule condition filters. Anything left unset uses the default: refurbished,
+/// used and B-stock listings are excluded, open-box, preorder and backorder
+/// listings are allowed.
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq, Eq, Clone)]
+#[serde(default)]
+pub struct ConditionFilters {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refurbished: Option<ConditionFilter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open_box: Option<ConditionFilter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub used: Option<ConditionFilter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub b_stock: Option<ConditionFilter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preorder: Option<ConditionFilter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backorder: Option<ConditionFilter>,
+}
+
+impl ConditionFilters {
+    pub const fn new() -> Self {
+        Self {
+            refurbished: None,
+            open_box: None,
+            used: None,
+            b_stock: None,
+            preorder: None,
+            backorder: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::new()
+    }
+
+    pub fn is_match(&self, condition: &Condition) -> bool {
+        use ConditionFilter::{Allow, Exclude};
+
+        self.refurbished.unwrap_or(Exclude).is_match(condition.refurbished)
+            && self.open_box.unwrap_or(Allow).is_match(condition.open_box)
+            && self.used.unwrap_or(Exclude).is_match(condition.used)
+            && self.b_stock.unwrap_or(Exclude).is_match(condition.b_stock)
+            && self.preorder.unwrap_or(Allow).is_match(condition.preorder)
+            && self.backorder.unwrap_or(Allow).is_match(condition.backorder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            Condition::detect("Amazon Renewed RTX 3080 open-box, B-Stock"),
+            Condition {
+                refurbished: true,
+                open_box: true,
+                b_stock: true,
+                ..Condition::default()
+            }
+        );
+        assert_eq!(
+            Condition::detect("Pre-order now, ships when backordered stock arrives"),
+            Condition {
+                preorder: true,
+                backorder: true,
+                ..Condition::default()
+            }
+        );
+        assert_eq!(Condition::detect("Samsung 990 Pro 2TB"), Condition::default());
+    }
+
+    #[test]
+    fn test_default_filters() {
+        let filters = ConditionFilters::new();
+
+        assert!(filters.is_match(&Condition::default()));
+        assert!(filters.is_match(&Condition { open_box: true, ..Condition::default() }));
+        assert!(!filters.is_match(&Condition { refurbished: true, ..Condition::default() }));
+        assert!(!filters.is_match(&Condition { used: true, ..Condition::default() }));
+    }
+
+    #[test]
+    fn test_opt_in_filters() {
+        let filters = ConditionFilters {
+            refurbished: Some(ConditionFilter::Allow),
+            open_box: Some(ConditionFilter::Only),
+            ..ConditionFilters::new()
+        };
+
+        assert!(filters.is_match(&Condition { refurbished: true, open_box: true, ..Condition::default() }));
+        assert!(!filters.is_match(&Condition::default()));
+    }
+}
@@ -2,7 +2,7 @@ use std::fs;
 
 use serde::Deserialize;
 
-use crate::{rule, reddit, discord, sms, error::Error, db};
+use crate::{comments, rule, reddit, discord, sms, error::Error, db, http, lifecycle, parser, retry, schedule, source, velocity, webhook};
 
 #[derive(Deserialize, PartialEq)]
 pub struct Config {
@@ -14,6 +14,29 @@ pub struct Config {
     pub discord: discord::Config,
     pub twilio: sms::Config,
     pub db: db::Config,
+    #[serde(default)]
+    pub http: http::Config,
+    #[serde(default)]
+    pub retry: retry::Config,
+    #[serde(default)]
+    pub schedule: schedule::Config,
+    #[serde(default)]
+    pub lifecycle: lifecycle::Config,
+    #[serde(default)]
+    pub velocity: velocity::Config,
+    #[serde(default)]
+    pub comments: comments::Config,
+    /// Run over the comments of matched posts
+    #[serde(default)]
+    pub comment_rules: Vec<comments::CommentRule>,
+    #[serde(default)]
+    pub title_parsers: Vec<parser::Config>,
+    /// Accept posts over HTTP when set
+    pub webhook: Option<webhook::Config>,
+    #[serde(skip_deserializing)]
+    pub sources: source::Sources,
+    #[serde(default, rename = "sources")]
+    sources_internal: Vec<source::Config>,
 }
 
 impl Config {
@@ -24,18 +47,36 @@ impl Config {
     }
 
     pub fn from_toml(source: &str) -> Result<Config, Error> {
-        let mut config: Self = toml::from_str(&source).map_err(|e| Error::Toml(e))?;
+        let mut config: Self = toml::from_str(source).map_err(Error::Toml)?;
         config.rules = rule::Rules {
             rules: config.rules_internal.clone()
         };
 
+        for source in &config.sources_internal {
+            source.validate()?;
+        }
+        config.sources = if config.sources_internal.is_empty() {
+            source::Sources(vec![source::Config::new(parser::DEFAULT_SOURCE)])
+        } else {
+            source::Sources(config.sources_internal.clone())
+        };
+
         Ok(config)
     }
+
+    /// The `[[title_parsers]]` entries plus any title parsers set on sources.
+    pub fn parser_configs(&self) -> Vec<parser::Config> {
+        self.title_parsers
+            .iter()
+            .cloned()
+            .chain(self.sources.parser_configs())
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::rule::{PatternAndSource, Pattern};
+    use crate::{condition::ConditionFilters, rule::{PatternAndSource, Pattern}};
 
     use super::*;
 
@@ -86,16 +127,24 @@ db_url = "sqlite://sqlite.db"
             auth_host: "https://www.reddit.com/api/v1/".to_owned(), 
             api_host: "https://oauth.reddit.com/".to_owned(), 
             token_file: "token.json".to_owned(), 
-            username: "<YOUR USERNAME>".to_owned(), 
-            password: "<YOUR USER'S PASSWORD>".to_owned(), 
+            token_passphrase: None,
+            grant: reddit::Grant::Password,
+            username: Some("<YOUR USERNAME>".to_owned()), 
+            password: Some("<YOUR USER'S PASSWORD>".into()), 
             client_id: "<YOUR API CLIENT ID>".to_owned(),
-            client_secret: "<YOUR API CLIENT SECRET>".to_owned(),
+            client_secret: "<YOUR API CLIENT SECRET>".into(),
             user_agent: "<YOUR API CLIENT USER AGENT>".to_owned(), 
-            wait_time_secs: 5 
+            scope: "read".to_owned(),
+            device_id: "DO_NOT_TRACK_THIS_DEVICE".to_owned(),
+            redirect_uri: "http://localhost:65010/authorize_callback".to_owned(),
+            wait_time_secs: 5,
+            page_size: 100,
+            max_pages: 10,
+            first_run_pages: 1,
         });
 
         assert_eq!(parsed.discord, discord::Config { 
-            token: "<YOUR DISCORD BOT TOKEN>".to_owned(),
+            token: "<YOUR DISCORD BOT TOKEN>".into(),
             user_agent: "<YOUR DISCORD BOT USER AGENT>".to_owned(),
             api_url: "https://discord.com/api/v10/".to_owned(),
             channel_id: "<YOUR DISCORD CHANNEL ID TO POST MESSAGES TO>".to_owned(),
@@ -119,9 +168,84 @@ db_url = "sqlite://sqlite.db"
                     }),
                     link_flair_pattern: None,
                     price_max_dollars: None,
-                    price_min_dollars: None
+                    price_min_dollars: None,
+                    price_per_tb_max_dollars: None,
+                    price_per_gb_max_dollars: None,
+                    attribute_filters: vec![],
+                    conditions: ConditionFilters::new(),
+                    subreddits: vec![],
+                    velocity_filters: vec![],
+                    body_pattern: None,
+                    domain_pattern: None,
+                    author_pattern: None,
+                    is_self: None,
+                    over_18: None,
+                    stickied: None,
+                    color: None,
                 }
             ]
-        })
+        });
+
+        assert_eq!(parsed.sources, source::Sources(vec![source::Config::new("buildapcsales")]));
+    }
+
+    #[test]
+    fn test_parse_config_sources() {
+        let toml_source = 
+r#"
+rules = []
+
+[[sources]]
+subreddit = "buildapcsales"
+
+[[sources]]
+subreddit = "bapcsalescanada"
+wait_time_secs = 30
+title_parser = "generic"
+currency = "CAD"
+
+[reddit]
+auth_host = ""
+api_host = ""
+token_file = ""
+username = ""
+password = ""
+client_id = ""
+client_secret = ""
+user_agent = ""
+wait_time_secs = 5
+
+[discord]
+token = ""
+user_agent = ""
+api_url = ""
+channel_id = ""
+sending_interval_secs = 10
+
+[twilio]
+api_url = ""
+api_key = ""
+api_key_secret = ""
+account_sid = ""
+phone_number_from = ""
+phone_number_to = ""
+
+[db]
+db_url = ""
+"#;
+
+        let parsed = Config::from_toml(toml_source).unwrap();
+
+        assert_eq!(parsed.sources.0.len(), 2);
+        assert_eq!(parsed.sources.currency("bapcsalescanada"), "CAD");
+        assert_eq!(parsed.sources.get("bapcsalescanada").unwrap().wait_time_secs, Some(30));
+        assert_eq!(parsed.parser_configs(), vec![parser::Config {
+            source: "bapcsalescanada".to_owned(),
+            kind: parser::Kind::Generic,
+            pattern: None,
+        }]);
+
+        let search = toml_source.replace("subreddit = \"bapcsalescanada\"", "name = \"cad\"\nkind = \"reddit_search\"\nquery = \"cad\"");
+        assert!(Config::from_toml(&search).is_err());
     }
 }
\ No newline at end of file
@@ -3,13 +3,14 @@ use std::{str::FromStr, hash::Hash, collections::hash_map::DefaultHasher};
 use serde::Deserialize;
 use sqlx::{Sqlite, SqlitePool, migrate::MigrateDatabase, sqlite::SqliteConnectOptions, ConnectOptions};
 
-use crate::{error::Error, models::{Post, Title}, rule};
+use crate::{backfill::Checkpoint, comments::Comment, error::Error, lifecycle::{DealStatus, TrackedPost}, models::{Post, Title}, rule, source::Cursor, velocity::Snapshot};
 
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Deserialize, Debug, PartialEq, Clone)]
 pub struct Config {
     pub db_url: String,
 }
 
+#[derive(Clone)]
 pub struct Client {
     config: Config,
     db: Option<SqlitePool>,
@@ -75,8 +76,9 @@ impl Client {
     pub async fn insert_post(&self, post: &Post) -> Result<bool, Error> {
         let db = self.get_db()?;
         let response = sqlx::query(
-            "INSERT OR IGNORE INTO posts (id, created_utc, downs, link_flair_text, title, ups, url)
-                  VALUES (?, ?, ?, ?, ?, ?, ?)")
+            "INSERT OR IGNORE INTO posts (id, created_utc, downs, link_flair_text, title, ups, url, subreddit, permalink,
+                                          selftext, author, domain, is_self, over_18, stickied, num_comments, thumbnail, source_kind)
+                  VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
             .bind(&post.id)
             .bind(post.created_utc)
             .bind(post.downs)
@@ -84,6 +86,17 @@ impl Client {
             .bind(&post.title)
             .bind(post.ups)
             .bind(&post.url)
+            .bind(&post.subreddit)
+            .bind(&post.permalink)
+            .bind(&post.selftext)
+            .bind(&post.author)
+            .bind(&post.domain)
+            .bind(post.is_self)
+            .bind(post.over_18)
+            .bind(post.stickied)
+            .bind(post.num_comments)
+            .bind(&post.thumbnail)
+            .bind(post.source_kind)
             .execute(db)
             .await?;   
     
@@ -92,15 +105,30 @@ impl Client {
 
     pub async fn insert_parsed_title(&self, title: &Title) -> Result<bool, Error> {
         let db = self.get_db()?;
+        let attributes = if title.attributes.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(&title.attributes)?)
+        };
         let response = sqlx::query(
-            "INSERT OR IGNORE INTO parsed_titles (post_id, product_type, description, price_dollars, price_cents, extra_details)
-                VALUES (?, ?, ?, ?, ?, ?)")
+            "INSERT OR IGNORE INTO parsed_titles (post_id, offer_index, product_type, description, price_dollars, price_cents, extra_details, attributes,
+                                                  refurbished, open_box, used, b_stock, preorder, backorder, currency)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
                 .bind(&title.post_id)
+                .bind(title.offer_index)
                 .bind(&title.product_type)
                 .bind(&title.description)
                 .bind(title.price_dollars)
                 .bind(title.price_cents)
                 .bind(&title.extra_details)
+                .bind(attributes)
+                .bind(title.condition.refurbished)
+                .bind(title.condition.open_box)
+                .bind(title.condition.used)
+                .bind(title.condition.b_stock)
+                .bind(title.condition.preorder)
+                .bind(title.condition.backorder)
+                .bind(&title.currency)
                 .execute(db)
                 .await?;
 
@@ -109,9 +137,32 @@ impl Client {
 
     pub async fn insert_rule(&self, rule: &rule::Rule) -> Result<bool, Error> {
         let db = self.get_db()?;
+        let attribute_filters = if rule.attribute_filters.is_empty() {
+            None
+        } else {
+            let sources: Vec<&String> = rule.attribute_filters.iter().map(|f| &f.source).collect();
+            Some(serde_json::to_string(&sources)?)
+        };
+        let condition_filters = if rule.conditions.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(&rule.conditions)?)
+        };
+        let subreddits = if rule.subreddits.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(&rule.subreddits)?)
+        };
+        let velocity_filters = if rule.velocity_filters.is_empty() {
+            None
+        } else {
+            let sources: Vec<&String> = rule.velocity_filters.iter().map(|f| &f.source).collect();
+            Some(serde_json::to_string(&sources)?)
+        };
         let response = sqlx::query(
-            "INSERT OR IGNORE INTO rules (id, name, link_flair_pattern, product_type_pattern, description_pattern, price_min, price_max)
-                 VALUES (?, ?, ?, ?, ?, ?, ?)")
+            "INSERT OR IGNORE INTO rules (id, name, link_flair_pattern, product_type_pattern, description_pattern, price_min, price_max, attribute_filters, price_per_tb_max, price_per_gb_max, condition_filters, subreddits, velocity_filters,
+                                           body_pattern, domain_pattern, author_pattern, is_self, over_18, stickied)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
                  .bind(rule.hash())
                  .bind(&rule.name)
                  .bind(rule.link_flair_pattern.as_ref().map(|p| &p.source))
@@ -120,24 +171,359 @@ impl Client {
                     &p.source))
                  .bind(rule.price_min_dollars)
                  .bind(rule.price_max_dollars)
+                 .bind(attribute_filters)
+                 .bind(rule.price_per_tb_max_dollars)
+                 .bind(rule.price_per_gb_max_dollars)
+                 .bind(condition_filters)
+                 .bind(subreddits)
+                 .bind(velocity_filters)
+                 .bind(rule.body_pattern.as_ref().map(|p| &p.source))
+                 .bind(rule.domain_pattern.as_ref().map(|p| &p.source))
+                 .bind(rule.author_pattern.as_ref().map(|p| &p.source))
+                 .bind(rule.is_self)
+                 .bind(rule.over_18)
+                 .bind(rule.stickied)
                  .execute(db)
                  .await?;
 
         Ok(response.rows_affected() > 0)
     }
 
-    pub async fn insert_rule_match(&self, post: &Post, rule: &rule::Rule) -> Result<bool, Error> {
+    pub async fn insert_rule_match(&self, post_id: &str, offer_index: i32, rule: &rule::Rule) -> Result<bool, Error> {
         let db = self.get_db()?;
         let response = sqlx::query(
-            "INSERT OR IGNORE INTO rule_matches (rule_id, post_id, created_utc)
-            VALUES (?, ?, ?)")
+            "INSERT OR IGNORE INTO rule_matches (rule_id, post_id, offer_index, created_utc)
+            VALUES (?, ?, ?, ?)")
             .bind(rule.hash())
+            .bind(post_id)
+            .bind(offer_index)
+            .bind(chrono::Utc::now().to_rfc3339())
+            .execute(db)
+            .await?;
+
+        Ok(response.rows_affected() > 0)
+    }
+
+    pub async fn insert_parse_failure(&self, post: &Post, parser: &str, parser_version: i64, reason: &str) -> Result<bool, Error> {
+        let db = self.get_db()?;
+        let response = sqlx::query(
+            "INSERT OR REPLACE INTO parse_failures (post_id, parser, parser_version, reason, created_utc)
+            VALUES (?, ?, ?, ?, ?)")
             .bind(&post.id)
+            .bind(parser)
+            .bind(parser_version)
+            .bind(reason)
             .bind(chrono::Utc::now().to_rfc3339())
             .execute(db)
             .await?;
 
         Ok(response.rows_affected() > 0)
     }
+
+    /// Clears a post's recorded parse failure once its title parses.
+    pub async fn delete_parse_failure(&self, post_id: &str) -> Result<(), Error> {
+        let db = self.get_db()?;
+        sqlx::query("DELETE FROM parse_failures WHERE post_id = ?")
+            .bind(post_id)
+            .execute(db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Stored posts created in `[since, until)` that don't have a parsed title yet.
+    pub async fn get_unparsed_posts(&self, since: f64, until: f64) -> Result<Vec<Post>, Error> {
+        let db = self.get_db()?;
+        let posts = sqlx::query_as::<_, Post>(
+            "SELECT p.id, CAST(p.created_utc AS REAL) AS created_utc, CAST(p.downs AS REAL) AS downs,
+                    p.link_flair_text, p.title, CAST(p.ups AS REAL) AS ups, COALESCE(p.url, '') AS url,
+                    COALESCE(p.subreddit, '') AS subreddit, COALESCE(p.permalink, '') AS permalink,
+                    COALESCE(p.selftext, '') AS selftext, p.author, COALESCE(p.domain, '') AS domain,
+                    COALESCE(p.is_self, 0) AS is_self, COALESCE(p.over_18, 0) AS over_18, COALESCE(p.stickied, 0) AS stickied,
+                    COALESCE(p.num_comments, 0) AS num_comments, p.thumbnail,
+                    COALESCE(p.source_kind, 'reddit') AS source_kind
+            FROM posts p
+            WHERE CAST(p.created_utc AS REAL) >= ? AND CAST(p.created_utc AS REAL) < ?
+              AND NOT EXISTS (SELECT 1 FROM parsed_titles t WHERE t.post_id = p.id)
+            ORDER BY CAST(p.created_utc AS REAL)")
+            .bind(since)
+            .bind(until)
+            .fetch_all(db)
+            .await?;
+
+        Ok(posts)
+    }
+
+    /// The newest post seen in a source, where polling resumes from.
+    pub async fn get_source_cursor(&self, source: &str) -> Result<Option<Cursor>, Error> {
+        let db = self.get_db()?;
+        let cursor: Option<(String, Option<f64>)> = sqlx::query_as(
+            "SELECT newest_fullname, newest_created_utc FROM source_cursors WHERE source = ?")
+            .bind(source)
+            .fetch_optional(db)
+            .await?;
+
+        Ok(cursor.map(|(id, created_utc)| Cursor { id, created_utc }))
+    }
+
+    pub async fn set_source_cursor(&self, source: &str, cursor: &Cursor) -> Result<(), Error> {
+        let db = self.get_db()?;
+        sqlx::query(
+            "INSERT INTO source_cursors (source, newest_fullname, newest_created_utc, updated_utc)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT (source) DO UPDATE SET newest_fullname = excluded.newest_fullname,
+                newest_created_utc = excluded.newest_created_utc, updated_utc = excluded.updated_utc")
+            .bind(source)
+            .bind(&cursor.id)
+            .bind(cursor.created_utc)
+            .bind(chrono::Utc::now().to_rfc3339())
+            .execute(db)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_backfill_checkpoint(&self, name: &str) -> Result<Option<Checkpoint>, Error> {
+        let db = self.get_db()?;
+        let checkpoint = sqlx::query_as(
+            "SELECT name, position, since_utc, finished FROM backfill_checkpoints WHERE name = ?")
+            .bind(name)
+            .fetch_optional(db)
+            .await?;
+
+        Ok(checkpoint)
+    }
+
+    pub async fn set_backfill_checkpoint(&self, checkpoint: &Checkpoint) -> Result<(), Error> {
+        let db = self.get_db()?;
+        sqlx::query(
+            "INSERT INTO backfill_checkpoints (name, position, since_utc, finished, updated_utc)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT (name) DO UPDATE SET position = excluded.position, since_utc = excluded.since_utc,
+                finished = excluded.finished, updated_utc = excluded.updated_utc")
+            .bind(&checkpoint.name)
+            .bind(&checkpoint.position)
+            .bind(checkpoint.since_utc)
+            .bind(checkpoint.finished)
+            .bind(chrono::Utc::now().to_rfc3339())
+            .execute(db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Matched Reddit posts created at or after `since` that haven't been
+    /// deleted or removed, with their last recorded status.
+    pub async fn get_tracked_posts(&self, since: f64) -> Result<Vec<TrackedPost>, Error> {
+        let db = self.get_db()?;
+        let rows: Vec<(String, String, Option<String>)> = sqlx::query_as(
+            "SELECT p.id, p.title,
+                    (SELECT s.status FROM post_statuses s WHERE s.post_id = p.id ORDER BY s.id DESC LIMIT 1) AS status
+            FROM posts p
+            WHERE CAST(p.created_utc AS REAL) >= ?
+              AND COALESCE(p.source_kind, 'reddit') = 'reddit'
+              AND EXISTS (SELECT 1 FROM rule_matches m WHERE m.post_id = p.id)
+            ORDER BY CAST(p.created_utc AS REAL)")
+            .bind(since)
+            .fetch_all(db)
+            .await?;
+
+        let mut posts = Vec::with_capacity(rows.len());
+        for (post_id, title, status) in rows {
+            let status = status.map(|status| status.parse::<DealStatus>()).transpose()?;
+            if status.is_some_and(DealStatus::is_final) {
+                continue;
+            }
+            posts.push(TrackedPost { post_id, title, status });
+        }
+
+        Ok(posts)
+    }
+
+    pub async fn insert_post_status(&self, post_id: &str, status: DealStatus, link_flair_text: Option<&str>) -> Result<(), Error> {
+        let db = self.get_db()?;
+        sqlx::query(
+            "INSERT INTO post_statuses (post_id, status, link_flair_text, checked_utc)
+            VALUES (?, ?, ?, ?)")
+            .bind(post_id)
+            .bind(status.as_str())
+            .bind(link_flair_text)
+            .bind(chrono::Utc::now().to_rfc3339())
+            .execute(db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Records the Discord message a match was sent in, so later updates can
+    /// reply to it.
+    pub async fn insert_notification(&self, post_id: &str, channel_id: &str, message_id: &str) -> Result<(), Error> {
+        let db = self.get_db()?;
+        sqlx::query(
+            "INSERT OR IGNORE INTO notifications (post_id, channel_id, message_id, created_utc)
+            VALUES (?, ?, ?, ?)")
+            .bind(post_id)
+            .bind(channel_id)
+            .bind(message_id)
+            .bind(chrono::Utc::now().to_rfc3339())
+            .execute(db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// The most recent message a post was sent in, as `(channel_id, message_id)`.
+    pub async fn get_notification(&self, post_id: &str) -> Result<Option<(String, String)>, Error> {
+        let db = self.get_db()?;
+        let notification = sqlx::query_as(
+            "SELECT channel_id, message_id FROM notifications WHERE post_id = ? ORDER BY created_utc DESC LIMIT 1")
+            .bind(post_id)
+            .fetch_optional(db)
+            .await?;
+
+        Ok(notification)
+    }
+
+    /// Records a comment matching a comment rule. Returns false once the
+    /// match has been notified, so each one is only sent once but a failed
+    /// send is tried again on the next scan.
+    pub async fn insert_comment_match(&self, post_id: &str, comment: &Comment, comment_rule: &str) -> Result<bool, Error> {
+        let db = self.get_db()?;
+        sqlx::query(
+            "INSERT OR IGNORE INTO comment_matches (comment_id, post_id, comment_rule, author, body, score, matched_utc)
+            VALUES (?, ?, ?, ?, ?, ?, ?)")
+            .bind(&comment.id)
+            .bind(post_id)
+            .bind(comment_rule)
+            .bind(&comment.author)
+            .bind(&comment.body)
+            .bind(comment.score)
+            .bind(chrono::Utc::now().to_rfc3339())
+            .execute(db)
+            .await?;
+
+        let (unnotified,): (bool,) = sqlx::query_as(
+            "SELECT notified_utc IS NULL FROM comment_matches WHERE comment_id = ?")
+            .bind(&comment.id)
+            .fetch_one(db)
+            .await?;
+
+        Ok(unnotified)
+    }
+
+    /// Marks a comment match as sent, or as not needing to be.
+    pub async fn set_comment_match_notified(&self, comment_id: &str) -> Result<(), Error> {
+        let db = self.get_db()?;
+        sqlx::query("UPDATE comment_matches SET notified_utc = ? WHERE comment_id = ?")
+            .bind(chrono::Utc::now().to_rfc3339())
+            .bind(comment_id)
+            .execute(db)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn has_rule_match(&self, post_id: &str, rule: &rule::Rule) -> Result<bool, Error> {
+        let db = self.get_db()?;
+        let found: Option<(i64,)> = sqlx::query_as(
+            "SELECT 1 FROM rule_matches WHERE post_id = ? AND rule_id = ? LIMIT 1")
+            .bind(post_id)
+            .bind(rule.hash())
+            .fetch_optional(db)
+            .await?;
+
+        Ok(found.is_some())
+    }
+
+    /// Ids of Reddit posts created at or after `since`, oldest first.
+    pub async fn get_recent_post_ids(&self, since: f64) -> Result<Vec<String>, Error> {
+        let db = self.get_db()?;
+        let ids: Vec<(String,)> = sqlx::query_as(
+            "SELECT id FROM posts
+            WHERE CAST(created_utc AS REAL) >= ? AND COALESCE(source_kind, 'reddit') = 'reddit'
+            ORDER BY CAST(created_utc AS REAL)")
+            .bind(since)
+            .fetch_all(db)
+            .await?;
+
+        Ok(ids.into_iter().map(|(id,)| id).collect())
+    }
+
+    pub async fn get_latest_snapshot(&self, post_id: &str) -> Result<Option<Snapshot>, Error> {
+        let db = self.get_db()?;
+        let snapshot: Option<(f64, i64, f64, i64)> = sqlx::query_as(
+            "SELECT taken_utc, score, upvote_ratio, num_comments FROM post_snapshots
+            WHERE post_id = ? ORDER BY taken_utc DESC LIMIT 1")
+            .bind(post_id)
+            .fetch_optional(db)
+            .await?;
+
+        Ok(snapshot.map(|(taken_utc, score, upvote_ratio, num_comments)| Snapshot {
+            taken_utc,
+            score,
+            upvote_ratio,
+            num_comments,
+        }))
+    }
+
+    /// Stores a snapshot and updates the post's vote count to match it.
+    pub async fn insert_snapshot(&self, post_id: &str, snapshot: &Snapshot, ups: f64, downs: f64) -> Result<(), Error> {
+        let db = self.get_db()?;
+        sqlx::query(
+            "INSERT OR IGNORE INTO post_snapshots (post_id, taken_utc, score, upvote_ratio, num_comments)
+            VALUES (?, ?, ?, ?, ?)")
+            .bind(post_id)
+            .bind(snapshot.taken_utc)
+            .bind(snapshot.score)
+            .bind(snapshot.upvote_ratio)
+            .bind(snapshot.num_comments)
+            .execute(db)
+            .await?;
+
+        sqlx::query("UPDATE posts SET ups = ?, downs = ? WHERE id = ?")
+            .bind(ups)
+            .bind(downs)
+            .bind(post_id)
+            .execute(db)
+            .await?;
+
+        Ok(())
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[tokio::test]
+    async fn test_comment_matches_until_notified() {
+        let dir = TempDir::new("db");
+        let mut db = Client::new(Config { db_url: dir.db_url() });
+        db.setup().await.unwrap();
+
+        let post: Post = serde_json::from_value(serde_json::json!({
+            "id": "abc",
+            "title": "[SSD] 990 Pro 2TB $149.99",
+            "created_utc": 0.0,
+            "ups": 1.0,
+            "downs": 0.0,
+            "url": "",
+            "link_flair_text": null,
+        })).unwrap();
+        db.insert_post(&post).await.unwrap();
+        let comment = Comment {
+            id: "c1".to_owned(),
+            author: None,
+            body: "code SAVE10 for 10% off".to_owned(),
+            score: 3,
+            permalink: String::new(),
+        };
+
+        // Still to be sent until it has been
+        assert!(db.insert_comment_match("abc", &comment, "Coupon code").await.unwrap());
+        assert!(db.insert_comment_match("abc", &comment, "Coupon code").await.unwrap());
+        db.set_comment_match_notified("c1").await.unwrap();
+        assert!(!db.insert_comment_match("abc", &comment, "Coupon code").await.unwrap());
+    }
+}
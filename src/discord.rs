@@ -1,11 +1,13 @@
+use std::{sync::Arc, time::{Duration, Instant}};
+
 use serde::{Deserialize, Serialize};
-use ureq::{Request, Response};
+use url::Url;
 
-use crate::error::Error;
+use crate::{error::Error, http::{HttpClient, Request, Response}, secret::Secret};
 
-#[derive(Deserialize, PartialEq, Debug)]
+#[derive(Deserialize, PartialEq, Debug, Clone)]
 pub struct Config {
-    pub token: String,
+    pub token: Secret<String>,
     pub user_agent: String,
     pub api_url: String,
     pub channel_id: String,
@@ -15,17 +17,40 @@ pub struct Config {
 pub struct Client {
     config: Config,
     ratelimit: Ratelimit,
+    http: Arc<dyn HttpClient>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Default)]
 pub struct CreateMessageRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub embeds: Option<Vec<Embed>>,
+    /// Sends the message as a reply
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_reference: Option<MessageReference>,
 }
 
 #[derive(Serialize)]
+pub struct MessageReference {
+    pub message_id: String,
+    /// Send the message anyway if the one being replied to is gone
+    pub fail_if_not_exists: bool,
+}
+
+impl MessageReference {
+    pub const fn reply_to(message_id: String) -> Self {
+        Self { message_id, fail_if_not_exists: false }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Message {
+    pub id: String,
+    pub channel_id: String,
+}
+
+#[derive(Serialize, Default)]
 pub struct Embed {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub title: Option<String>,
@@ -33,6 +58,111 @@ pub struct Embed {
     pub description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub url: Option<String>,
+    /// ISO 8601, shown in the footer in the reader's time zone
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<Color>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub footer: Option<EmbedFooter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<EmbedImage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<Field>,
+}
+
+// Discord's limits on embeds, in characters
+// https://discord.com/developers/docs/resources/message#embed-object-embed-limits
+const MAX_EMBEDS: usize = 10;
+/// Across all the embeds in a message
+const MAX_MESSAGE_EMBED_CHARS: usize = 6000;
+const MAX_TITLE_CHARS: usize = 256;
+const MAX_DESCRIPTION_CHARS: usize = 4096;
+const MAX_FIELDS: usize = 25;
+const MAX_FIELD_NAME_CHARS: usize = 256;
+const MAX_FIELD_VALUE_CHARS: usize = 1024;
+const MAX_FOOTER_CHARS: usize = 2048;
+
+impl Embed {
+    /// Cuts the text down to Discord's limits, so the embed can't get a
+    /// message rejected. If it's still too long for a message on its own,
+    /// fields are dropped from the end and then the description.
+    pub fn truncate_to_limits(mut self) -> Self {
+        for (text, max_chars) in [(&mut self.title, MAX_TITLE_CHARS), (&mut self.description, MAX_DESCRIPTION_CHARS)] {
+            if let Some(text) = text {
+                *text = truncate(text, max_chars);
+            }
+        }
+        if let Some(footer) = &mut self.footer {
+            footer.text = truncate(&footer.text, MAX_FOOTER_CHARS);
+        }
+        self.fields.truncate(MAX_FIELDS);
+        for field in &mut self.fields {
+            field.name = truncate(&field.name, MAX_FIELD_NAME_CHARS);
+            field.value = truncate(&field.value, MAX_FIELD_VALUE_CHARS);
+        }
+
+        while self.chars() > MAX_MESSAGE_EMBED_CHARS && self.fields.pop().is_some() {}
+        if self.chars() > MAX_MESSAGE_EMBED_CHARS {
+            self.description = None;
+        }
+        self
+    }
+
+    /// Characters that count towards the limit for a whole message.
+    pub fn chars(&self) -> usize {
+        let count = |text: &Option<String>| text.as_deref().map_or(0, |text| text.chars().count());
+        count(&self.title)
+            + count(&self.description)
+            + self.footer.as_ref().map_or(0, |footer| footer.text.chars().count())
+            + self.fields.iter().map(|field| field.name.chars().count() + field.value.chars().count()).sum::<usize>()
+    }
+}
+
+/// Shortens `text` to at most `max_chars` characters, the `…` marking where
+/// it was cut included, so it stays within Discord's limits.
+pub fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().nth(max_chars).is_none() {
+        return text.to_owned();
+    }
+    let Some(kept) = max_chars.checked_sub(1) else {
+        return String::new();
+    };
+
+    let end = text.char_indices().nth(kept).map_or(text.len(), |(i, _)| i);
+    format!("{}…", &text[..end])
+}
+
+/// Splits embeds, in order, into as few groups as possible that each fit in
+/// one message.
+pub fn chunk_embeds(embeds: Vec<Embed>) -> Vec<Vec<Embed>> {
+    let mut chunks: Vec<Vec<Embed>> = Vec::new();
+    let mut chunk_chars = 0;
+    for embed in embeds {
+        let embed = embed.truncate_to_limits();
+        let chars = embed.chars();
+        match chunks.last_mut() {
+            Some(chunk) if chunk.len() < MAX_EMBEDS && chunk_chars + chars <= MAX_MESSAGE_EMBED_CHARS => {
+                chunk_chars += chars;
+                chunk.push(embed);
+            }
+            _ => {
+                chunk_chars = chars;
+                chunks.push(vec![embed]);
+            }
+        }
+    }
+    chunks
+}
+
+#[derive(Serialize)]
+pub struct EmbedImage {
+    pub url: String,
+}
+
+#[derive(Serialize)]
+pub struct EmbedFooter {
+    pub text: String,
 }
 
 #[derive(Serialize)]
@@ -42,6 +172,37 @@ pub struct Field {
     pub inline: bool,
 }
 
+impl Field {
+    /// A field shown side by side with other inline fields.
+    pub fn inline(name: &str, value: impl Into<String>) -> Self {
+        Self { name: name.to_owned(), value: value.into(), inline: true }
+    }
+}
+
+/// The color of the bar down the side of an embed, written as `"#rrggbb"` in
+/// config.
+#[derive(Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(transparent)]
+pub struct Color(pub u32);
+
+impl std::str::FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.strip_prefix('#')
+            .filter(|hex| hex.len() == 6)
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .map(Self)
+            .ok_or_else(|| format!("expected a color like \"#ff8800\", got {s:?}"))
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 // X-RateLimit-Limit: 5
 // X-RateLimit-Remaining: 0
 // X-RateLimit-Reset: 1470173023
@@ -49,34 +210,49 @@ pub struct Field {
 // X-RateLimit-Bucket: abcd1234
 struct Ratelimit {
     remaining: u32,
+    reset_at: Option<Instant>,
 }
 
 impl Client {
-    pub const fn new(config: Config) -> Self {
+    pub fn new(config: Config, http: Arc<dyn HttpClient>) -> Self {
         Self {
             config,
-            ratelimit: Ratelimit { remaining: 1 }
+            ratelimit: Ratelimit { remaining: 1, reset_at: None },
+            http,
         }
     }
 
     fn add_headers(&self, request: Request) -> Request {
-        let auth_payload = format!("Bot {}", self.config.token);
-        request.set("Authorization", &auth_payload)
-            .set("User-Agent", &self.config.user_agent)
+        let auth_payload = format!("Bot {}", self.config.token.expose());
+        request.header("Authorization", &auth_payload)
+            .header("User-Agent", &self.config.user_agent)
     }
 
     fn update_ratelimits(&mut self, response: &Response) -> Result<(), Error> {
         self.ratelimit.remaining = response.header("X-RateLimit-Remaining")
             .ok_or(Error::MissingHeader("X-RateLimit-Remaining".to_owned()))?
             .parse().map_err(Error::ParseInt)?;
+        self.ratelimit.reset_at = response.header("X-RateLimit-Reset-After")
+            .and_then(|secs| secs.parse::<f64>().ok())
+            .map(|secs| Instant::now() + Duration::from_secs_f64(secs.max(0.0)));
 
         log::info!("{} requests remaining", self.ratelimit.remaining);
 
         Ok(())
     }
 
+    /// How long until more messages can be sent, if the rate limit has been
+    /// used up.
+    pub fn ratelimit_wait(&self) -> Option<Duration> {
+        if self.ratelimit.remaining > 0 {
+            return None;
+        }
+        self.ratelimit.reset_at.map(|reset_at| reset_at.saturating_duration_since(Instant::now()))
+    }
+
     fn check_ratelimit(&self) -> Result<(), Error> {
-        if self.ratelimit.remaining == 0 {
+        let reset = self.ratelimit.reset_at.is_some_and(|reset_at| reset_at <= Instant::now());
+        if self.ratelimit.remaining == 0 && !reset {
             return Err(Error::OutOfRequests);
         }
 
@@ -84,15 +260,75 @@ impl Client {
     }
 
     // POST /channels/{channel.id}/messages
-    pub fn create_message(&mut self, body: &CreateMessageRequest) -> Result<(), Error> {
+    pub async fn create_message(&mut self, body: &CreateMessageRequest) -> Result<Message, Error> {
         self.check_ratelimit()?;
 
         log::info!("create_message body {}", serde_json::to_string(body)?);
-        let url = format!("{}channels/{}/messages", self.config.api_url, self.config.channel_id);
-        let resp = self.add_headers(ureq::post(&url))
-            .send_json(body)
-            .map_err(Box::new)?;
+        let url = Url::parse(&format!("{}channels/{}/messages", self.config.api_url, self.config.channel_id))?;
+        let request = self.add_headers(Request::post(url)).json(body)?;
+        let resp = self.http.send(request).await?.error_for_status()?;
         self.update_ratelimits(&resp)?;
-        Ok(())
+        resp.json()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn embed(title_chars: usize, description_chars: usize) -> Embed {
+        Embed {
+            title: Some("t".repeat(title_chars)),
+            description: Some("d".repeat(description_chars)),
+            fields: vec![Field::inline("Price", "$1.00")],
+            ..Embed::default()
+        }
+    }
+
+    #[test]
+    fn test_truncate_to_limits() {
+        // Fields go before the description
+        let shortened = Embed { fields: (0..3).map(|i| Field::inline(&i.to_string(), "v".repeat(1000))).collect(), ..embed(100, 3000) }.truncate_to_limits();
+        assert_eq!(shortened.fields.len(), 2);
+        assert_eq!(shortened.description.as_deref().unwrap().chars().count(), 3000);
+
+        let embed = Embed {
+            footer: Some(EmbedFooter { text: "f".repeat(3000) }),
+            fields: (0..30).map(|i| Field::inline(&i.to_string(), "v".repeat(2000))).collect(),
+            ..embed(300, 5000)
+        }
+        .truncate_to_limits();
+
+        let title = embed.title.as_deref().unwrap();
+        assert_eq!(title.chars().count(), MAX_TITLE_CHARS);
+        assert!(title.ends_with('…'));
+        assert_eq!(embed.footer.as_ref().unwrap().text.chars().count(), MAX_FOOTER_CHARS);
+        assert!(embed.fields.iter().all(|field| field.value.chars().count() == MAX_FIELD_VALUE_CHARS));
+        // Still has to fit in a message on its own
+        assert!(embed.chars() <= MAX_MESSAGE_EMBED_CHARS);
+        assert!(embed.fields.is_empty());
+        assert!(embed.description.is_none());
+
+        assert_eq!(truncate("ÄÖÜ", 2), "Ä…");
+        assert_eq!(truncate("Ä…", 2), "Ä…");
+        assert_eq!(truncate("ÄÖÜ", 3), "ÄÖÜ");
+        assert_eq!(truncate("ÄÖÜ", 0), "");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_chunk_embeds() {
+        let titles = |chunks: &[Vec<Embed>]| chunks.iter().map(|chunk| chunk.iter().map(|embed| embed.title.clone().unwrap()).collect::<Vec<_>>()).collect::<Vec<_>>();
+
+        // At most 10 embeds a message, in order
+        let chunks = chunk_embeds((0..23).map(|i| Embed { title: Some(i.to_string()), ..Embed::default() }).collect());
+        assert_eq!(chunks.iter().map(Vec::len).collect::<Vec<_>>(), vec![10, 10, 3]);
+        assert_eq!(titles(&chunks)[2], vec!["20", "21", "22"]);
+
+        // At most 6000 characters a message
+        let chunks = chunk_embeds((0..5).map(|_| embed(100, 2000)).collect());
+        assert_eq!(chunks.iter().map(Vec::len).collect::<Vec<_>>(), vec![2, 2, 1]);
+        assert!(chunks.iter().all(|chunk| chunk.iter().map(Embed::chars).sum::<usize>() <= MAX_MESSAGE_EMBED_CHARS));
+
+        assert!(chunk_embeds(Vec::new()).is_empty());
+    }
+}
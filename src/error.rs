@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use thiserror::Error;
 
 use crate::rule;
@@ -7,7 +9,13 @@ pub enum Error {
     #[error("url parsing error: {0}")]
     Url(#[from] url::ParseError),
     #[error("request error: {0}")]
-    Ureq(#[from] Box<ureq::Error>),
+    Http(#[from] reqwest::Error),
+    #[error("http status {status}: {body}")]
+    HttpStatus {
+        status: u16,
+        retry_after: Option<Duration>,
+        body: String,
+    },
     #[error("json error: {0}")]
     Serde(#[from] serde_json::Error),
     #[error("toml error: {0}")]
@@ -28,6 +36,45 @@ pub enum Error {
     ParseInt(#[from] std::num::ParseIntError),
     #[error("sqlx error: {0}")]
     Sqlx(#[from] sqlx::Error),
+    #[error("regex error: {0}")]
+    Regex(#[from] regex::Error),
     #[error("rule error: {0}")]
     Rule(#[from] rule::Error),
-}
\ No newline at end of file
+    #[error("feed error: {0}")]
+    Feed(String),
+}
+
+impl Error {
+    /// Whether the failed operation might succeed if it's tried again later,
+    /// e.g. timeouts, 5xx and 429 responses, or a truncated response body.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Http(e) => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body() || e.is_decode(),
+            Self::HttpStatus { status, .. } => matches!(status, 401 | 408 | 429 | 500..=599),
+            Self::Serde(_)
+            | Self::Reauthenticate
+            | Self::OutOfRequests
+            | Self::MissingHeader(_)
+            | Self::ParseFloat(_)
+            | Self::ParseInt(_) => true,
+            Self::Url(_)
+            | Self::Toml(_)
+            | Self::Io(_)
+            | Self::Other(_)
+            | Self::Sqlx(_)
+            | Self::Regex(_)
+            | Self::Rule(_)
+            // A feed that was fetched but can't be read will read the same
+            // way next time
+            | Self::Feed(_) => false,
+        }
+    }
+
+    /// How long the server asked us to wait before retrying, if it did.
+    pub const fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::HttpStatus { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use std::sync::{Arc, LazyLock};
+
+use chrono::DateTime;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use url::Url;
+
+use crate::{error::Error, http::{BoxFuture, HttpClient, Request}, models::Post, source::{Cursor, Kind, Source}};
+
+/// Where each field of a JSON feed item is, as dot-separated paths like
+/// `authors.0.name`. The defaults follow JSON Feed 1.1.
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct JsonFields {
+    /// Path to the list of items, empty if the document is the list
+    #[serde(default = "default_items")]
+    pub items: String,
+    #[serde(default = "default_id")]
+    pub id: String,
+    #[serde(default = "default_title")]
+    pub title: String,
+    #[serde(default = "default_url")]
+    pub url: String,
+    /// Either seconds since the epoch or an RFC 3339/RFC 2822 date
+    #[serde(default = "default_created")]
+    pub created: String,
+    #[serde(default = "default_author")]
+    pub author: String,
+    #[serde(default = "default_body")]
+    pub body: String,
+    #[serde(default = "default_thumbnail")]
+    pub thumbnail: String,
+}
+
+fn default_items() -> String {
+    "items".to_owned()
+}
+
+fn default_id() -> String {
+    "id".to_owned()
+}
+
+fn default_title() -> String {
+    "title".to_owned()
+}
+
+fn default_url() -> String {
+    "url".to_owned()
+}
+
+fn default_created() -> String {
+    "date_published".to_owned()
+}
+
+fn default_author() -> String {
+    "authors.0.name".to_owned()
+}
+
+fn default_body() -> String {
+    "content_text".to_owned()
+}
+
+fn default_thumbnail() -> String {
+    "image".to_owned()
+}
+
+impl Default for JsonFields {
+    fn default() -> Self {
+        Self {
+            items: default_items(),
+            id: default_id(),
+            title: default_title(),
+            url: default_url(),
+            created: default_created(),
+            author: default_author(),
+            body: default_body(),
+            thumbnail: default_thumbnail(),
+        }
+    }
+}
+
+/// An RSS or Atom feed.
+pub struct RssSource {
+    name: String,
+    url: String,
+    http: Arc<dyn HttpClient>,
+}
+
+impl RssSource {
+    pub fn new(name: &str, url: &str, http: Arc<dyn HttpClient>) -> Self {
+        Self {
+            name: name.to_owned(),
+            url: url.to_owned(),
+            http,
+        }
+    }
+}
+
+impl Source for RssSource {
+    fn fetch_new<'a>(&'a mut self, last_seen: Option<&'a Cursor>) -> BoxFuture<'a, Result<Vec<Post>, Error>> {
+        Box::pin(async move {
+            let body = fetch(self.http.as_ref(), &self.url).await?;
+            let posts = parse_feed(&self.name, body.as_bytes())?;
+            Ok(take_new(posts, last_seen.map(|cursor| cursor.id.as_str())))
+        })
+    }
+}
+
+/// A JSON document with a list of items, like a JSON Feed or a shop's API.
+pub struct JsonFeedSource {
+    name: String,
+    url: String,
+    fields: JsonFields,
+    http: Arc<dyn HttpClient>,
+}
+
+impl JsonFeedSource {
+    pub fn new(name: &str, url: &str, fields: JsonFields, http: Arc<dyn HttpClient>) -> Self {
+        Self {
+            name: name.to_owned(),
+            url: url.to_owned(),
+            fields,
+            http,
+        }
+    }
+}
+
+impl Source for JsonFeedSource {
+    fn fetch_new<'a>(&'a mut self, last_seen: Option<&'a Cursor>) -> BoxFuture<'a, Result<Vec<Post>, Error>> {
+        Box::pin(async move {
+            let body = fetch(self.http.as_ref(), &self.url).await?;
+            let document: Value = serde_json::from_str(&body).map_err(|e| Error::Feed(format!("couldn't parse {}: {e}", self.name)))?;
+            let posts = parse_json_feed(&self.name, &self.fields, &document)?;
+            Ok(take_new(posts, last_seen.map(|cursor| cursor.id.as_str())))
+        })
+    }
+}
+
+pub(crate) async fn fetch(http: &dyn HttpClient, url: &str) -> Result<String, Error> {
+    let response = http.send(Request::get(Url::parse(url)?)).await?;
+    Ok(response.error_for_status()?.body)
+}
+
+/// An entry from any kind of feed, before it becomes a `Post`.
+#[derive(Debug)]
+pub(crate) struct Item {
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    pub created_utc: Option<f64>,
+    pub author: Option<String>,
+    pub body: String,
+    pub thumbnail: Option<String>,
+    /// Shown like a Reddit flair, e.g. a stock status
+    pub flair: Option<String>,
+}
+
+impl Item {
+    #[allow(clippy::cast_precision_loss)]
+    pub fn into_post(self, source: &str, kind: Kind) -> Post {
+        // Reddit's own feeds use fullnames, so their posts keep the ids the
+        // API gives them and aren't stored twice. They're Reddit posts like
+        // any other, so deal checks, snapshots and comment scans cover them
+        // too. Other ids, including any submitted to the webhook, are only
+        // unique within their source.
+        let (id, kind) = match self.id.strip_prefix("t3_").filter(|_| kind == Kind::Rss) {
+            Some(id) => (id.to_owned(), Kind::Reddit),
+            None => (format!("{source}:{}", self.id), kind),
+        };
+        let domain = Url::parse(&self.url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.trim_start_matches("www.").to_owned()))
+            .unwrap_or_default();
+
+        Post {
+            created_utc: self.created_utc.unwrap_or_else(|| chrono::Utc::now().timestamp() as f64),
+            downs: 0.0,
+            link_flair_text: self.flair,
+            title: self.title,
+            ups: 0.0,
+            url: self.url.clone(),
+            id,
+            subreddit: source.to_owned(),
+            permalink: self.url,
+            selftext: self.body,
+            author: self.author,
+            domain,
+            is_self: false,
+            over_18: false,
+            stickied: false,
+            num_comments: 0,
+            thumbnail: self.thumbnail,
+            source_kind: kind,
+        }
+    }
+}
+
+/// The first image in an entry's HTML, used as its thumbnail.
+static IMAGE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"<img[^>]+src="([^"]+)""#).unwrap());
+
+/// Parses an RSS, Atom or JSON Feed document, in feed order.
+#[allow(clippy::cast_precision_loss)]
+pub fn parse_feed(source: &str, body: &[u8]) -> Result<Vec<Post>, Error> {
+    let feed = feed_rs::parser::parse(body).map_err(|e| Error::Feed(format!("couldn't parse {source}: {e}")))?;
+
+    Ok(feed
+        .entries
+        .into_iter()
+        .map(|entry| {
+            let body = entry
+                .content
+                .and_then(|content| content.body)
+                .or_else(|| entry.summary.map(|summary| summary.content))
+                .unwrap_or_default();
+            // Reddit names authors like "/u/someone"
+            let author = entry
+                .authors
+                .into_iter()
+                .find_map(|person| person.name)
+                .map(|name| name.trim_start_matches("/u/").to_owned());
+            // Lone media:thumbnail elements are dropped by the parser, so
+            // fall back to the first image in the content, which is where
+            // Reddit puts its thumbnails
+            let thumbnail = entry
+                .media
+                .into_iter()
+                .flat_map(|media| media.thumbnails)
+                .map(|thumbnail| thumbnail.image.uri)
+                .next()
+                .or_else(|| IMAGE.captures(&body).map(|c| c[1].replace("&amp;", "&")));
+
+            Item {
+                id: entry.id,
+                title: entry.title.map(|title| title.content).unwrap_or_default(),
+                url: entry.links.into_iter().next().map(|link| link.href).unwrap_or_default(),
+                created_utc: entry.published.or(entry.updated).map(|date| date.timestamp() as f64),
+                author,
+                body,
+                thumbnail,
+                flair: None,
+            }
+            .into_post(source, Kind::Rss)
+        })
+        .collect())
+}
+
+/// Pulls items out of a JSON document using `fields`, in document order.
+/// Items without an id or title are skipped.
+pub fn parse_json_feed(source: &str, fields: &JsonFields, document: &Value) -> Result<Vec<Post>, Error> {
+    let items = lookup(document, &fields.items)
+        .and_then(Value::as_array)
+        .ok_or_else(|| Error::Feed(format!("no list of items at {:?} in {source}", fields.items)))?;
+
+    let mut posts = Vec::with_capacity(items.len());
+    for item in items {
+        let text = |path: &str| lookup(item, path).and_then(as_text);
+        let (Some(id), Some(title)) = (text(&fields.id), text(&fields.title)) else {
+            log::warn!("Skipping item without an id or title in {source}");
+            continue;
+        };
+
+        posts.push(Item {
+            id,
+            title,
+            url: text(&fields.url).unwrap_or_default(),
+            created_utc: lookup(item, &fields.created).and_then(as_timestamp),
+            author: text(&fields.author),
+            body: text(&fields.body).unwrap_or_default(),
+            thumbnail: text(&fields.thumbnail),
+            flair: None,
+        }
+        .into_post(source, Kind::JsonFeed));
+    }
+
+    Ok(posts)
+}
+
+/// Follows a dot-separated path through objects and arrays.
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.is_empty() {
+        return Some(value);
+    }
+
+    path.split('.').try_fold(value, |value, key| match value {
+        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        Value::Object(map) => map.get(key),
+        _ => None,
+    })
+}
+
+fn as_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn as_timestamp(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => DateTime::parse_from_rfc3339(s)
+            .or_else(|_| DateTime::parse_from_rfc2822(s))
+            .map(|date| date.timestamp() as f64)
+            .ok()
+            .or_else(|| s.parse().ok()),
+        _ => None,
+    }
+}
+
+/// Posts newer than the one with id `last_seen`, oldest first. Feeds are
+/// usually newest first, but are sorted by date to be sure.
+fn take_new(mut posts: Vec<Post>, last_seen: Option<&str>) -> Vec<Post> {
+    posts.sort_by(|a, b| b.created_utc.total_cmp(&a.created_utc));
+    if let Some(i) = last_seen.and_then(|last_seen| posts.iter().position(|post| post.id == last_seen)) {
+        posts.truncate(i);
+    }
+    posts.reverse();
+    posts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::StandInResponse;
+
+    fn ids(posts: &[Post]) -> Vec<&str> {
+        posts.iter().map(|post| post.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_broken_feeds_arent_retried() {
+        let url = crate::testing::serve(|request| match request.path() {
+            "/deals.json" => StandInResponse::ok("application/json", "<html>Down for maintenance</html>"),
+            "/deals.rss" => StandInResponse::ok("application/rss+xml", "not a feed"),
+            "/busy.rss" => StandInResponse::status(503),
+            _ => StandInResponse::status(404),
+        })
+        .await;
+        let http = crate::http::ReqwestClient::shared(&crate::http::Config::default()).unwrap();
+        let fetch_error = |path: &str| {
+            let url = url.join(path).unwrap().to_string();
+            let mut source: Box<dyn Source> = if path.ends_with(".json") {
+                Box::new(JsonFeedSource::new("shop", &url, JsonFields::default(), http.clone()))
+            } else {
+                Box::new(RssSource::new("shop", &url, http.clone()))
+            };
+            async move { source.fetch_new(None).await.unwrap_err() }
+        };
+
+        assert!(matches!(fetch_error("/deals.json").await, Error::Feed(_)));
+        assert!(!fetch_error("/deals.json").await.is_retryable());
+        assert!(!fetch_error("/deals.rss").await.is_retryable());
+        assert!(!fetch_error("/gone.rss").await.is_retryable());
+        assert!(fetch_error("/busy.rss").await.is_retryable());
+    }
+
+    #[test]
+    fn test_parse_reddit_feed() {
+        let posts = parse_feed("buildapcsales", include_bytes!("../tests/fixtures/feeds/reddit.atom")).unwrap();
+
+        assert_eq!(ids(&posts), vec!["1g2xk9a", "1g2wq3b"]);
+        let post = &posts[0];
+        assert_eq!(post.title, "[SSD] Samsung 990 Pro 2TB NVMe M.2 SSD $149.99");
+        assert_eq!(post.author.as_deref(), Some("dealfinder"));
+        assert_eq!(post.subreddit, "buildapcsales");
+        assert_eq!(post.source_kind, Kind::Reddit);
+        assert_eq!(post.get_comments_url(), "https://www.reddit.com/r/buildapcsales/comments/1g2xk9a/ssd_samsung_990_pro_2tb/");
+        assert_eq!(post.thumbnail.as_deref(), Some("https://b.thumbs.redditmedia.com/abc.jpg"));
+    }
+
+    #[test]
+    fn test_parse_rss_feed() {
+        let posts = parse_feed("forum", include_bytes!("../tests/fixtures/feeds/deals.rss")).unwrap();
+
+        assert_eq!(ids(&posts), vec!["forum:deal-1002", "forum:deal-1001"]);
+        assert_eq!(posts[0].title, "[GPU] RX 7800 XT 16GB $449");
+        assert_eq!(posts[0].url, "https://forum.example.com/deals/1002");
+        assert_eq!(posts[0].domain, "forum.example.com");
+        assert_eq!(posts[0].created_utc, 1_729_252_800.0);
+        assert!(posts[1].selftext.contains("Ships free"));
+
+        let new = take_new(posts, Some("forum:deal-1001"));
+        assert_eq!(ids(&new), vec!["forum:deal-1002"]);
+    }
+
+    #[test]
+    fn test_parse_json_feed() {
+        let document: Value = serde_json::from_str(include_str!("../tests/fixtures/feeds/deals.json")).unwrap();
+        let posts = parse_json_feed("jsonfeed", &JsonFields::default(), &document).unwrap();
+
+        assert_eq!(ids(&posts), vec!["jsonfeed:a1", "jsonfeed:a2"]);
+        assert_eq!(posts[0].author.as_deref(), Some("Deal Bot"));
+        assert_eq!(posts[0].thumbnail.as_deref(), Some("https://example.com/a1.png"));
+        assert_eq!(posts[1].created_utc, 1_729_249_200.0);
+        assert_eq!(ids(&take_new(posts, None)), vec!["jsonfeed:a2", "jsonfeed:a1"]);
+    }
+
+    #[test]
+    fn test_parse_custom_json_feed() {
+        let document: Value = serde_json::from_str(include_str!("../tests/fixtures/feeds/shop.json")).unwrap();
+        let fields: JsonFields = toml::from_str(r#"
+            items = "data.deals"
+            id = "sku"
+            title = "name"
+            url = "links.product"
+            created = "posted_at"
+            body = "notes"
+        "#).unwrap();
+        let posts = parse_json_feed("shop", &fields, &document).unwrap();
+
+        // The item without a name is skipped
+        assert_eq!(ids(&posts), vec!["shop:12345"]);
+        assert_eq!(posts[0].title, "[RAM] 32GB DDR5-6000 $89.99");
+        assert_eq!(posts[0].url, "https://shop.example.com/p/12345");
+        assert_eq!(posts[0].created_utc, 1_729_260_000.0);
+
+        assert!(parse_json_feed("shop", &JsonFields::default(), &document).is_err());
+    }
+}
@@ -0,0 +1,275 @@
+use std::{collections::HashMap, fmt, future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use url::Url;
+
+use crate::error::Error;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Sends HTTP requests for the API clients. Implementations are shared between
+/// tasks, so they should reuse connections.
+pub trait HttpClient: Send + Sync {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<Response, Error>>;
+}
+
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct Config {
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// Sent with requests that don't set their own, like feed fetches
+    #[serde(default = "default_user_agent")]
+    pub user_agent: String,
+}
+
+const fn default_timeout_secs() -> u64 {
+    30
+}
+
+const fn default_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_user_agent() -> String {
+    concat!("sales_crawler/", env!("CARGO_PKG_VERSION")).to_owned()
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            timeout_secs: default_timeout_secs(),
+            connect_timeout_secs: default_connect_timeout_secs(),
+            user_agent: default_user_agent(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+}
+
+#[derive(Clone)]
+pub struct Request {
+    pub method: Method,
+    pub url: Url,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+}
+
+impl Request {
+    pub fn new(method: Method, url: Url) -> Self {
+        Self {
+            method,
+            url,
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    pub fn get(url: Url) -> Self {
+        Self::new(Method::Get, url)
+    }
+
+    pub fn post(url: Url) -> Self {
+        Self::new(Method::Post, url)
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub fn query(mut self, name: &str, value: &str) -> Self {
+        self.url.query_pairs_mut().append_pair(name, value);
+        self
+    }
+
+    pub fn json<T: Serialize + ?Sized>(self, body: &T) -> Result<Self, Error> {
+        let body = serde_json::to_string(body)?;
+        Ok(self.body("application/json", body))
+    }
+
+    pub fn form(self, fields: &[(&str, &str)]) -> Self {
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(fields)
+            .finish();
+        self.body("application/x-www-form-urlencoded", body)
+    }
+
+    fn body(mut self, content_type: &str, body: String) -> Self {
+        self.body = Some(body);
+        self.header("Content-Type", content_type)
+    }
+}
+
+/// Headers whose values are credentials, matched ignoring case
+const SENSITIVE_HEADERS: [&str; 4] = ["authorization", "proxy-authorization", "cookie", "x-api-key"];
+
+/// Leaves out credential headers and the body, which holds the secrets of
+/// token requests, so requests can be logged.
+impl fmt::Debug for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let headers: Vec<(&str, &str)> = self.headers
+            .iter()
+            .map(|(name, value)| {
+                let sensitive = SENSITIVE_HEADERS.iter().any(|sensitive| name.eq_ignore_ascii_case(sensitive));
+                (name.as_str(), if sensitive { "<redacted>" } else { value.as_str() })
+            })
+            .collect();
+
+        f.debug_struct("Request")
+            .field("method", &self.method)
+            .field("url", &self.url.as_str())
+            .field("headers", &headers)
+            .field("body", &self.body.as_ref().map(|body| format!("<{} bytes>", body.len())))
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    /// Header names are lowercase
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+impl Response {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(String::as_str)
+    }
+
+    pub const fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+
+    /// Turns a non-2xx response into an `Error::HttpStatus`.
+    pub fn error_for_status(self) -> Result<Self, Error> {
+        if self.is_success() {
+            Ok(self)
+        } else {
+            Err(Error::HttpStatus {
+                status: self.status,
+                retry_after: self.retry_after(),
+                body: self.body,
+            })
+        }
+    }
+
+    /// The `Retry-After` header, when it's given in seconds.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.header("Retry-After")?
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|secs| secs.is_finite() && *secs >= 0.0)
+            .map(Duration::from_secs_f64)
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        Ok(serde_json::from_str(&self.body)?)
+    }
+}
+
+/// `HttpClient` on top of a pooled `reqwest::Client`.
+pub struct ReqwestClient {
+    client: reqwest::Client,
+}
+
+impl ReqwestClient {
+    pub fn new(config: &Config) -> Result<Self, Error> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+            .user_agent(&config.user_agent)
+            .build()?;
+
+        Ok(Self { client })
+    }
+
+    pub fn shared(config: &Config) -> Result<Arc<dyn HttpClient>, Error> {
+        Ok(Arc::new(Self::new(config)?))
+    }
+}
+
+impl HttpClient for ReqwestClient {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<Response, Error>> {
+        Box::pin(async move {
+            let method = match request.method {
+                Method::Get => reqwest::Method::GET,
+                Method::Post => reqwest::Method::POST,
+            };
+
+            let mut builder = self.client.request(method, request.url);
+            for (name, value) in &request.headers {
+                builder = builder.header(name, value);
+            }
+            if let Some(body) = request.body {
+                builder = builder.body(body);
+            }
+
+            let response = builder.send().await?;
+            let status = response.status().as_u16();
+            let headers = response
+                .headers()
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.as_str().to_lowercase(), value.to_str().ok()?.to_owned()))
+                })
+                .collect();
+            let body = response.text().await?;
+
+            Ok(Response { status, headers, body })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_builder() {
+        let request = Request::post(Url::parse("https://example.com/api?a=1").unwrap())
+            .query("limit", "100")
+            .form(&[("Body", "hi there!"), ("To", "+1555")]);
+
+        assert_eq!(request.url.as_str(), "https://example.com/api?a=1&limit=100");
+        assert_eq!(request.body.as_deref(), Some("Body=hi+there%21&To=%2B1555"));
+        assert_eq!(request.headers, vec![("Content-Type".to_owned(), "application/x-www-form-urlencoded".to_owned())]);
+    }
+
+    #[test]
+    fn test_request_debug_redacts_credentials() {
+        let request = Request::post(Url::parse("https://example.com/token").unwrap())
+            .header("authorization", "Bearer hunter2")
+            .form(&[("password", "hunter2")]);
+
+        let debug = format!("{request:?}");
+        assert!(!debug.contains("hunter2"));
+        assert!(debug.contains(r#"("authorization", "<redacted>")"#));
+        assert!(debug.contains("application/x-www-form-urlencoded"));
+    }
+
+    #[test]
+    fn test_response_headers_and_status() {
+        let response = Response {
+            status: 429,
+            headers: HashMap::from([
+                ("x-ratelimit-remaining".to_owned(), "0".to_owned()),
+                ("retry-after".to_owned(), "7".to_owned()),
+            ]),
+            body: "slow down".to_owned(),
+        };
+
+        assert_eq!(response.header("X-Ratelimit-Remaining"), Some("0"));
+        let err = response.error_for_status().unwrap_err();
+        assert!(matches!(err, Error::HttpStatus { status: 429, .. }));
+        assert!(err.is_retryable());
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(7)));
+    }
+}
@@ -0,0 +1,226 @@
+use std::{fmt, str::FromStr, sync::LazyLock};
+
+use regex::Regex;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use crate::{db, error::Error, poll::NotifyMessage, reddit};
+
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct Config {
+    /// How often matched posts are re-fetched
+    #[serde(default = "default_check_interval_secs")]
+    pub check_interval_secs: u64,
+    /// Stop checking posts older than this
+    #[serde(default = "default_max_age_hours")]
+    pub max_age_hours: u64,
+}
+
+const fn default_check_interval_secs() -> u64 {
+    600
+}
+
+const fn default_max_age_hours() -> u64 {
+    48
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            check_interval_secs: default_check_interval_secs(),
+            max_age_hours: default_max_age_hours(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DealStatus {
+    Active,
+    Expired,
+    OutOfStock,
+    Deleted,
+    Removed,
+}
+
+static EXPIRED_FLAIR: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\bexpired\b").unwrap());
+static OUT_OF_STOCK_FLAIR: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\b(?:oos|out of stock|sold out)\b").unwrap());
+
+impl DealStatus {
+    /// Status of a post as returned by `/by_id`. Deletion and removal win
+    /// over the flair, since the flair usually stays behind.
+    pub fn detect(post: &PostState) -> Self {
+        match post.removed_by_category.as_deref() {
+            Some("deleted" | "author") => return Self::Deleted,
+            Some(_) => return Self::Removed,
+            None => {}
+        }
+        if post.author.as_deref() == Some("[deleted]") {
+            return Self::Deleted;
+        }
+
+        let flair = post.link_flair_text.as_deref().unwrap_or_default();
+        if EXPIRED_FLAIR.is_match(flair) {
+            Self::Expired
+        } else if OUT_OF_STOCK_FLAIR.is_match(flair) {
+            Self::OutOfStock
+        } else {
+            Self::Active
+        }
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Active => "active",
+            Self::Expired => "expired",
+            Self::OutOfStock => "out_of_stock",
+            Self::Deleted => "deleted",
+            Self::Removed => "removed",
+        }
+    }
+
+    /// Deleted and removed posts can't come back, so they aren't checked again.
+    pub const fn is_final(self) -> bool {
+        matches!(self, Self::Deleted | Self::Removed)
+    }
+}
+
+impl FromStr for DealStatus {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "active" => Ok(Self::Active),
+            "expired" => Ok(Self::Expired),
+            "out_of_stock" => Ok(Self::OutOfStock),
+            "deleted" => Ok(Self::Deleted),
+            "removed" => Ok(Self::Removed),
+            other => Err(Error::Other(format!("unknown deal status {other}"))),
+        }
+    }
+}
+
+impl fmt::Display for DealStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Active => "active again",
+            Self::Expired => "expired",
+            Self::OutOfStock => "out of stock",
+            Self::Deleted => "deleted",
+            Self::Removed => "removed",
+        })
+    }
+}
+
+/// The parts of a post needed to tell whether a deal is still live.
+#[derive(Deserialize, Debug)]
+pub struct PostState {
+    pub id: String,
+    pub link_flair_text: Option<String>,
+    pub removed_by_category: Option<String>,
+    pub author: Option<String>,
+}
+
+/// A matched post and the last status recorded for it.
+#[derive(Debug)]
+pub struct TrackedPost {
+    pub post_id: String,
+    pub title: String,
+    pub status: Option<DealStatus>,
+}
+
+/// Re-fetches recently matched posts, records status changes, and tells the
+/// notify loop about deals that have changed since they were sent.
+pub async fn check_deals(db: &db::Client, reddit_client: &mut reddit::Client, config: &Config, tx: &mpsc::Sender<NotifyMessage>) -> Result<(), Error> {
+    let since = chrono::Utc::now().timestamp() as f64 - (config.max_age_hours * 3600) as f64;
+    let tracked = db.get_tracked_posts(since).await?;
+    log::info!("Checking {} matched posts for expired deals", tracked.len());
+
+    for chunk in tracked.chunks(100) {
+        let fullnames: Vec<String> = chunk.iter().map(|post| format!("t3_{}", post.post_id)).collect();
+        let states: Vec<PostState> = reddit_client.by_id(&fullnames).await?;
+
+        for post in chunk {
+            let state = states.iter().find(|state| state.id == post.post_id);
+            let Some((status, notify)) = status_change(post, state) else {
+                continue;
+            };
+
+            db.insert_post_status(&post.post_id, status, state.and_then(|state| state.link_flair_text.as_deref())).await?;
+            log::info!("Post {} is now {}", post.post_id, status.as_str());
+
+            if notify {
+                tx.send(NotifyMessage::DealStatusChanged {
+                    post_id: post.post_id.clone(),
+                    title: post.title.clone(),
+                    status,
+                })
+                .await
+                .map_err(|e| Error::Other(e.to_string()))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The status to record for a tracked post if it has changed, and whether
+/// to send it. `/by_id` leaves out some removed posts altogether, so a post
+/// without a `state` has been removed. The first check of a deal that's still
+/// active is only recorded, but one that's already gone is sent straight
+/// away.
+fn status_change(post: &TrackedPost, state: Option<&PostState>) -> Option<(DealStatus, bool)> {
+    let status = state.map_or(DealStatus::Removed, DealStatus::detect);
+    if post.status == Some(status) {
+        return None;
+    }
+    Some((status, post.status.is_some() || status != DealStatus::Active))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(flair: Option<&str>, removed_by_category: Option<&str>, author: &str) -> PostState {
+        PostState {
+            id: "abc".to_owned(),
+            link_flair_text: flair.map(str::to_owned),
+            removed_by_category: removed_by_category.map(str::to_owned),
+            author: Some(author.to_owned()),
+        }
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(DealStatus::detect(&state(None, None, "user")), DealStatus::Active);
+        assert_eq!(DealStatus::detect(&state(Some("Expired"), None, "user")), DealStatus::Expired);
+        assert_eq!(DealStatus::detect(&state(Some("OOS"), None, "user")), DealStatus::OutOfStock);
+        assert_eq!(DealStatus::detect(&state(Some("Sold Out"), None, "user")), DealStatus::OutOfStock);
+        assert_eq!(DealStatus::detect(&state(Some("Expired"), Some("moderator"), "user")), DealStatus::Removed);
+        assert_eq!(DealStatus::detect(&state(None, Some("deleted"), "[deleted]")), DealStatus::Deleted);
+        assert_eq!(DealStatus::detect(&state(None, None, "[deleted]")), DealStatus::Deleted);
+    }
+
+    #[test]
+    fn test_status_changes() {
+        let tracked = |status| TrackedPost { post_id: "abc".to_owned(), title: String::new(), status };
+        let active = state(None, None, "user");
+        let expired = state(Some("Expired"), None, "user");
+
+        // First checks
+        assert_eq!(status_change(&tracked(None), Some(&active)), Some((DealStatus::Active, false)));
+        assert_eq!(status_change(&tracked(None), Some(&expired)), Some((DealStatus::Expired, true)));
+        assert_eq!(status_change(&tracked(None), None), Some((DealStatus::Removed, true)));
+
+        assert_eq!(status_change(&tracked(Some(DealStatus::Active)), Some(&active)), None);
+        assert_eq!(status_change(&tracked(Some(DealStatus::Active)), Some(&expired)), Some((DealStatus::Expired, true)));
+        assert_eq!(status_change(&tracked(Some(DealStatus::Expired)), Some(&active)), Some((DealStatus::Active, true)));
+        assert_eq!(status_change(&tracked(Some(DealStatus::Active)), None), Some((DealStatus::Removed, true)));
+    }
+
+    #[test]
+    fn test_status_roundtrip() {
+        for status in [DealStatus::Active, DealStatus::Expired, DealStatus::OutOfStock, DealStatus::Deleted, DealStatus::Removed] {
+            assert_eq!(status.as_str().parse::<DealStatus>().unwrap(), status);
+        }
+    }
+}
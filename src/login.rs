@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+
+use rand::{distributions::Alphanumeric, Rng};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpListener};
+use url::Url;
+
+use crate::{config, error::Error, http, reddit};
+
+/// One-time authorization code flow: prints Reddit's authorize page, waits for
+/// the redirect on `reddit.redirect_uri` and saves the resulting refresh token
+/// to the token file.
+pub async fn login(config: config::Config) -> Result<(), Error> {
+    let http = http::ReqwestClient::shared(&config.http)?;
+    let mut reddit_client = reddit::Client::new(config.reddit, http);
+
+    let redirect_uri = Url::parse(&reddit_client.config.redirect_uri)?;
+    let host = redirect_uri.host_str().unwrap_or("localhost");
+    let port = redirect_uri.port_or_known_default().unwrap_or(80);
+    let listener = TcpListener::bind((host, port)).await?;
+
+    let state: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+    println!("Open this URL to authorize the app:\n\n{}\n", reddit_client.authorize_url(&state)?);
+    println!("Waiting for the redirect to {redirect_uri}");
+
+    let code = loop {
+        let (mut stream, _) = listener.accept().await?;
+        let mut buf = vec![0; 8192];
+        let n = stream.read(&mut buf).await?;
+        let request = String::from_utf8_lossy(&buf[..n]);
+
+        let result = parse_callback(&request, redirect_uri.path(), &state);
+        let body = match &result {
+            Ok(Some(_)) => "Authorized, you can close this window.".to_owned(),
+            Ok(None) => "Not found".to_owned(),
+            Err(e) => format!("Authorization failed: {e}"),
+        };
+        let status = if matches!(result, Ok(None)) { "404 Not Found" } else { "200 OK" };
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).await?;
+
+        if let Some(code) = result? {
+            break code;
+        }
+    };
+
+    reddit_client.authenticate_with_code(&code).await?;
+    println!("Saved refresh token to {}", reddit_client.config.token_file);
+
+    Ok(())
+}
+
+/// Pulls the authorization code out of a raw redirect request. Requests for
+/// other paths, like `/favicon.ico`, give `None`.
+fn parse_callback(request: &str, path: &str, state: &str) -> Result<Option<String>, Error> {
+    let target = request
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .ok_or_else(|| Error::Other("malformed redirect request".to_owned()))?;
+    let url = Url::parse("http://localhost")?.join(target)?;
+    if url.path() != path {
+        return Ok(None);
+    }
+
+    let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+    if let Some(error) = params.get("error") {
+        return Err(Error::Other(format!("Reddit returned error {error}")));
+    }
+    if params.get("state").map(String::as_str) != Some(state) {
+        return Err(Error::Other("state doesn't match".to_owned()));
+    }
+
+    params
+        .get("code")
+        .cloned()
+        .map(Some)
+        .ok_or_else(|| Error::Other("no code in redirect".to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_callback() {
+        let request = "GET /authorize_callback?state=abc&code=xyz HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        assert_eq!(parse_callback(request, "/authorize_callback", "abc").unwrap(), Some("xyz".to_owned()));
+        assert!(parse_callback(request, "/authorize_callback", "other").is_err());
+
+        let favicon = "GET /favicon.ico HTTP/1.1\r\n\r\n";
+        assert_eq!(parse_callback(favicon, "/authorize_callback", "abc").unwrap(), None);
+
+        let denied = "GET /authorize_callback?state=abc&error=access_denied HTTP/1.1\r\n\r\n";
+        assert!(parse_callback(denied, "/authorize_callback", "abc").is_err());
+    }
+}
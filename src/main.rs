@@ -1,13 +1,34 @@
+mod attributes;
 mod auth;
+mod backfill;
+mod comments;
 mod error;
+mod feed;
+mod http;
+mod lifecycle;
+mod login;
 mod reddit;
 mod models;
 mod rule;
 mod sms;
+mod source;
+mod velocity;
 mod discord;
 mod db;
+mod parser;
 mod poll;
+mod condition;
 mod config;
+mod reprocess;
+mod retry;
+mod schedule;
+mod scrape;
+mod secret;
+mod webhook;
+#[cfg(test)]
+mod testing;
+use std::path::PathBuf;
+
 use error::Error;
 
 use clap::{Parser, Subcommand, CommandFactory};
@@ -24,6 +45,48 @@ struct Args {
 enum Commands {
     Setup,
     Poll,
+    /// Re-run the title parsers and rules over stored posts that failed to parse
+    Reprocess {
+        /// Only posts created at or after this date (YYYY-MM-DD or RFC 3339)
+        #[arg(long)]
+        since: String,
+        /// Only posts created before this date, defaults to now
+        #[arg(long)]
+        until: Option<String>,
+        /// Send notifications for new matches on posts created within this many hours
+        #[arg(long)]
+        notify_within_hours: Option<u64>,
+    },
+    /// Store older posts, their parsed titles and rule matches without sending
+    /// notifications, paging back through a subreddit or reading a dump.
+    /// Posts stored this way count as already seen, so if `poll` is running
+    /// at the same time, new posts the backfill gets to first are never
+    /// notified.
+    Backfill {
+        /// Go back to posts created at this date (YYYY-MM-DD or RFC 3339)
+        #[arg(long)]
+        since: String,
+        /// Subreddit to page back through, or to keep posts from when reading a dump
+        #[arg(long)]
+        subreddit: Option<String>,
+        /// JSON lines dump to read instead of Reddit, one post per line
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Start over instead of resuming from the last checkpoint
+        #[arg(long)]
+        restart: bool,
+    },
+    /// Manage Reddit authorization
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AuthCommands {
+    /// Authorize an account for the authorization_code grant and save its refresh token
+    Login,
 }
 
 #[tokio::main]
@@ -42,6 +105,31 @@ async fn main() -> Result<(), Error> {
             let config = config::Config::read_from_toml_file("config.toml")?;
             polling_loop(config).await?;
         }
+        Some(Commands::Reprocess { since, until, notify_within_hours }) => {
+            let config = config::Config::read_from_toml_file("config.toml")?;
+            let since = reprocess::parse_timestamp(since)?;
+            let until = match until {
+                Some(until) => reprocess::parse_timestamp(until)?,
+                None => reprocess::now_timestamp(),
+            };
+            let notify_since = notify_within_hours
+                .map(|hours| reprocess::now_timestamp() - (hours * 3600) as f64);
+            reprocess::reprocess(config, since, until, notify_since).await?;
+        }
+        Some(Commands::Backfill { since, subreddit, file, restart }) => {
+            let config = config::Config::read_from_toml_file("config.toml")?;
+            let since = reprocess::parse_timestamp(since)?;
+            let target = match (file, subreddit) {
+                (Some(path), subreddit) => backfill::Target::File { path: path.clone(), subreddit: subreddit.clone() },
+                (None, Some(subreddit)) => backfill::Target::Subreddit(subreddit.clone()),
+                (None, None) => return Err(Error::Other("backfill needs a --subreddit or a --file".to_owned())),
+            };
+            backfill::backfill(config, target, since, *restart).await?;
+        }
+        Some(Commands::Auth { command: AuthCommands::Login }) => {
+            let config = config::Config::read_from_toml_file("config.toml")?;
+            login::login(config).await?;
+        }
         _ => {
             Args::command().print_help()?;
         }
@@ -1,10 +1,12 @@
+use std::sync::LazyLock;
+
 use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 use sqlx::FromRow;
 
-use crate::rule::{self};
+use crate::{attributes::{Attributes, Category}, condition::Condition, parser::{self, ParseError}, rule::{self}, source};
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Debug, FromRow)]
 pub struct Post {
     pub created_utc: f64,
     pub downs: f64,
@@ -13,16 +15,113 @@ pub struct Post {
     pub ups: f64,
     pub url: String,
     pub id: String,
+    #[serde(default)]
+    pub subreddit: String,
+    #[serde(default)]
+    pub permalink: String,
+    /// Body of a self post, empty for link posts
+    #[serde(default)]
+    pub selftext: String,
+    #[serde(default)]
+    pub author: Option<String>,
+    /// Domain of the linked page, or `self.<subreddit>` for self posts
+    #[serde(default)]
+    pub domain: String,
+    #[serde(default)]
+    pub is_self: bool,
+    #[serde(default)]
+    pub over_18: bool,
+    #[serde(default)]
+    pub stickied: bool,
+    #[serde(default)]
+    pub num_comments: i64,
+    /// Full-size preview image of the linked page, if Reddit made one
+    #[serde(default, rename = "preview", deserialize_with = "deserialize_thumbnail")]
+    pub thumbnail: Option<String>,
+    /// Posts from the Reddit API can be re-fetched by id, others can't
+    #[serde(default)]
+    pub source_kind: source::Kind,
+}
+
+/// Picks the first preview image's URL out of a post's `preview` object.
+fn deserialize_thumbnail<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Preview {
+        #[serde(default)]
+        images: Vec<PreviewImage>,
+    }
+    #[derive(Deserialize)]
+    struct PreviewImage {
+        source: PreviewSource,
+    }
+    #[derive(Deserialize)]
+    struct PreviewSource {
+        url: String,
+    }
+
+    let preview: Option<Preview> = Option::deserialize(deserializer)?;
+    // Reddit HTML-escapes preview URLs
+    Ok(preview
+        .and_then(|preview| preview.images.into_iter().next())
+        .map(|image| image.source.url.replace("&amp;", "&")))
 }
 
 impl Post {
+    /// Reddit's type-prefixed id for the post, as used by listing cursors.
+    pub fn fullname(&self) -> String {
+        format!("t3_{}", self.id)
+    }
+
     pub fn get_comments_url(&self) -> String {
-        format!("https://www.reddit.com/r/buildapcsales/comments/{}", self.id)
+        // Posts from feeds link straight to their page
+        if self.permalink.starts_with("http") {
+            return self.permalink.clone();
+        }
+        if !self.permalink.is_empty() {
+            return format!("https://www.reddit.com{}", self.permalink);
+        }
+
+        let subreddit = if self.subreddit.is_empty() { parser::DEFAULT_SOURCE } else { &self.subreddit };
+        format!("https://www.reddit.com/r/{subreddit}/comments/{}", self.id)
     }
 }
 
 impl rule::Subject for Post {
     fn is_match(&self, rule: &rule::Rule) -> bool {
+        if !rule.subreddits.is_empty()
+            && !rule.subreddits.iter().any(|s| s.eq_ignore_ascii_case(&self.subreddit))
+        {
+            return false;
+        }
+
+        if let Some(ref body_pattern) = rule.body_pattern {
+            if !body_pattern.pattern.does_string_match(&self.selftext) {
+                return false;
+            }
+        }
+
+        if let Some(ref domain_pattern) = rule.domain_pattern {
+            if !domain_pattern.pattern.does_string_match(&self.domain) {
+                return false;
+            }
+        }
+
+        if let Some(ref author_pattern) = rule.author_pattern {
+            if !author_pattern.pattern.does_string_option_match(&self.author) {
+                return false;
+            }
+        }
+
+        if rule.is_self.is_some_and(|is_self| is_self != self.is_self)
+            || rule.over_18.is_some_and(|over_18| over_18 != self.over_18)
+            || rule.stickied.is_some_and(|stickied| stickied != self.stickied)
+        {
+            return false;
+        }
+
         match &rule.link_flair_pattern {
             Some(link_flair_pattern) => 
                 link_flair_pattern.pattern.does_string_option_match(&self.link_flair_text),
@@ -31,7 +130,7 @@ impl rule::Subject for Post {
     }
 }
 
-#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[derive(Deserialize, Debug, PartialEq)]
 pub struct Title {
     pub post_id: String,
     pub product_type: String,
@@ -39,42 +138,248 @@ pub struct Title {
     pub price_dollars: i32,
     pub price_cents: i8,
     pub extra_details: Option<String>,
+    #[serde(default)]
+    pub attributes: Attributes,
+    #[serde(default)]
+    pub offer_index: i32,
+    #[serde(default)]
+    pub condition: Condition,
+    #[serde(default = "default_currency")]
+    pub currency: String,
 }
 
+fn default_currency() -> String {
+    source::DEFAULT_CURRENCY.to_owned()
+}
+
+/// Words that introduce a price other than the deal's, like `was $449`.
+const ORIGINAL_PRICE_WORDS: &str = r"was|reg(?:ular|\.)?|retail|msrp|list|orig(?:inal|\.)?";
+
+static WAS_PRICE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(r"(?i)\b(?:{ORIGINAL_PRICE_WORDS})\b[\s:.]*\$(?P<amount>\d[\d,]*(?:\.\d+)?)")).unwrap()
+});
+static AMOUNT_OFF: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\$(?P<amount>\d[\d,]*(?:\.\d+)?)\s*off\b").unwrap());
+static PERCENT_OFF: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)(?P<percent>\d+(?:\.\d+)?)\s*%\s*off\b").unwrap());
+
+/// The `[GPU]` style tag starting a buildapcsales title.
+static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[(?P<type>[ \w]+)\]").unwrap());
+
 impl Title {
-    pub fn parse(title: &str, post_id: &str) -> Option<Self> {
-        let re = Regex::new(r"\[(?P<type>[ \w]+)\](?P<desc>[^$]*)\$(?P<price_dollars>\d+)(\.(?P<price_cents>\d+))?(?P<extra>[^\d].*)?").ok()?;
-        match re.captures(title) {
-            Some(m) => {
-                let product_type = m.name("type")?.as_str().trim().to_owned();
-                let description = m.name("desc")?.as_str().trim().to_owned();
-
-                let price_dollars = m.name("price_dollars")?;
-                let price_dollars = price_dollars.as_str().parse().ok()?;
-
-                let price_cents = m.name("price_cents");
-                let price_cents: i8 = price_cents.map_or(Some(0), |price_cents| {
-                    price_cents.as_str().parse().ok()
-                })?;
-
-                let extra_details = m.name("extra").map(|s| s.as_str().trim().to_owned());
-
-                Some(Self {
-                    post_id: post_id.to_owned(),
-                    product_type,
-                    description,
-                    price_dollars,
-                    price_cents,
-                    extra_details,
-                })
+    pub fn new(
+        post_id: &str,
+        product_type: String,
+        description: String,
+        price_dollars: i32,
+        price_cents: i8,
+        extra_details: Option<String>,
+    ) -> Self {
+        let attributes = Attributes::extract(&product_type, &description);
+        let condition = Condition::detect(&format!(
+            "{product_type} {description} {}",
+            extra_details.as_deref().unwrap_or_default()
+        ));
+
+        Self {
+            post_id: post_id.to_owned(),
+            product_type,
+            description,
+            price_dollars,
+            price_cents,
+            extra_details,
+            attributes,
+            offer_index: 0,
+            condition,
+            currency: default_currency(),
+        }
+    }
+
+    /// Parses a title in the r/buildapcsales format. Titles listing several
+    /// prices, like `[RAM] 2x16GB $89 / 2x32GB $159`, produce one `Title` per
+    /// offer. Prices in parentheses are treated as details of the offer before
+    /// them rather than as offers of their own.
+    pub fn parse(title: &str, post_id: &str) -> Result<Vec<Self>, ParseError> {
+        let Some(tag) = TAG.captures(title) else {
+            if title.contains('$') {
+                return Err(ParseError::NoMatch("buildapcsales".to_owned()));
+            }
+            return Err(ParseError::NoPrice);
+        };
+        let product_type = tag["type"].trim().to_owned();
+        let rest = &title[tag.get(0).unwrap().end()..];
+
+        let prices = prices_outside_parens(rest)?;
+        let Some(first) = prices.first() else {
+            return Err(ParseError::NoPrice);
+        };
+
+        let mut offers = vec![Offer {
+            description: rest[..first.start].trim().to_owned(),
+            price: first,
+            details_end: rest.len(),
+        }];
+        for price in &prices[1..] {
+            let previous = offers.last_mut().unwrap();
+            let between = &rest[previous.price.end..price.start];
+            let Some((separator_start, separator_end)) = last_offer_separator(between) else {
+                continue;
+            };
+
+            let description = between[separator_end..].trim();
+            if !description.chars().any(char::is_alphanumeric) {
+                continue;
             }
-            _ => None,
+
+            // The text up to the separator is details for the previous offer
+            previous.details_end = previous.price.end + separator_start;
+            offers.push(Offer {
+                description: description.to_owned(),
+                price,
+                details_end: rest.len(),
+            });
+        }
+
+        let titles = offers
+            .into_iter()
+            .enumerate()
+            .map(|(i, offer)| {
+                let extra_details = rest[offer.price.end..offer.details_end].trim();
+                let extra_details = (!extra_details.is_empty()).then(|| extra_details.to_owned());
+
+                let mut title = Self::new(
+                    post_id,
+                    product_type.clone(),
+                    offer.description,
+                    offer.price.dollars,
+                    offer.price.cents,
+                    extra_details,
+                );
+                title.offer_index = i as i32;
+                title
+            })
+            .collect();
+
+        Ok(titles)
+    }
+
+    pub fn price(&self) -> f64 {
+        f64::from(self.price_dollars) + 0.01 * f64::from(self.price_cents)
+    }
+
+    pub fn price_per_gb(&self) -> Option<f64> {
+        self.attributes
+            .capacity_gb
+            .filter(|gb| *gb > 0.0)
+            .map(|gb| self.price() / gb)
+    }
+
+    pub fn price_per_tb(&self) -> Option<f64> {
+        self.attributes
+            .capacity_tb()
+            .filter(|tb| *tb > 0.0)
+            .map(|tb| self.price() / tb)
+    }
+
+    /// Unit price in whichever unit is customary for the product type, e.g.
+    /// `$45.00/TB` for storage or `$2.81/GB` for memory.
+    pub fn unit_price_display(&self) -> Option<String> {
+        let symbol = source::currency_symbol(&self.currency);
+        match Category::from_product_type(&self.product_type) {
+            Some(Category::Storage) => self.price_per_tb().map(|p| format!("{symbol}{p:.2}/TB")),
+            _ => self.price_per_gb().map(|p| format!("{symbol}{p:.2}/GB")),
+        }
+    }
+
+    /// The price before the deal, from details like `(was $199.99)`,
+    /// `(MSRP $599)` or `($50 off)`.
+    pub fn original_price(&self) -> Option<f64> {
+        let details = self.extra_details.as_deref()?;
+        let amount = |m: regex::Match| m.as_str().replace(',', "").parse::<f64>().ok();
+
+        if let Some(original) = WAS_PRICE.captures(details).and_then(|c| amount(c.name("amount")?)) {
+            return Some(original).filter(|original| *original > self.price());
+        }
+
+        AMOUNT_OFF.captures(details)
+            .and_then(|c| amount(c.name("amount")?))
+            .map(|off| self.price() + off)
+    }
+
+    /// How much cheaper than `original_price`, as given by
+    /// [`Self::original_price`], the deal is, in percent, or as given in
+    /// details like `(25% off)`.
+    pub fn discount_percent(&self, original_price: Option<f64>) -> Option<f64> {
+        if let Some(original) = original_price {
+            return Some(100.0 * (1.0 - self.price() / original));
+        }
+
+        PERCENT_OFF.captures(self.extra_details.as_deref()?)?["percent"].parse().ok()
+    }
+}
+
+struct PriceToken {
+    start: usize,
+    end: usize,
+    dollars: i32,
+    cents: i8,
+}
+
+struct Offer<'a> {
+    description: String,
+    price: &'a PriceToken,
+    details_end: usize,
+}
+
+static PRICE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\$(?P<dollars>\d(?:[\d,]*\d)?)(?:\.(?P<cents>\d+))?").unwrap());
+static OFFER_SEPARATOR: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[/|;,]").unwrap());
+static ORIGINAL_PRICE_WORD: LazyLock<Regex> = LazyLock::new(|| Regex::new(&format!(r"(?i)^\s*(?:{ORIGINAL_PRICE_WORDS})[\s:.]*$")).unwrap());
+
+/// Every `$1,234.56` in `s` that isn't inside parentheses. If all of them are,
+/// the first one is used so the title still gets a price.
+fn prices_outside_parens(s: &str) -> Result<Vec<PriceToken>, ParseError> {
+    let mut all = Vec::new();
+    let mut outside = Vec::new();
+    for m in PRICE.captures_iter(s) {
+        let whole = m.get(0).unwrap();
+        let dollars = m["dollars"]
+            .replace(',', "")
+            .parse()
+            .map_err(|_| ParseError::InvalidPrice(whole.as_str().to_owned()))?;
+        let cents = match m.name("cents") {
+            Some(cents) => parser::parse_cents(cents.as_str())
+                .ok_or_else(|| ParseError::InvalidPrice(whole.as_str().to_owned()))?,
+            None => 0,
+        };
+
+        let before = &s[..whole.start()];
+        let depth = before.matches('(').count() as i64 - before.matches(')').count() as i64;
+        let token = PriceToken { start: whole.start(), end: whole.end(), dollars, cents };
+        if depth <= 0 {
+            outside.push(token);
+        } else {
+            all.push(token);
         }
     }
 
-    fn price(&self) -> f64 {
-        (self.price_dollars as f64) + 0.1 * (self.price_cents as f64)
+    if outside.is_empty() {
+        all.truncate(1);
+        return Ok(all);
     }
+    Ok(outside)
+}
+
+/// The last `/`, `|`, `;` or `,` in `s`, the text between two prices. A slash
+/// right after a letter, as in `w/`, doesn't count, and neither does a comma
+/// joining a price to its details, as in `$349, was $449`. `or` isn't a
+/// separator either.
+fn last_offer_separator(s: &str) -> Option<(usize, usize)> {
+    OFFER_SEPARATOR.find_iter(s)
+        .filter(|m| match m.as_str() {
+            "/" => !s[..m.start()].chars().next_back().is_some_and(char::is_alphabetic),
+            "," => !ORIGINAL_PRICE_WORD.is_match(&s[m.end()..]),
+            _ => true,
+        })
+        .last()
+        .map(|m| (m.start(), m.end()))
 }
 
 impl rule::Subject for Title {
@@ -103,10 +408,31 @@ impl rule::Subject for Title {
             }
         }
 
+        if let Some(ref price_per_tb_max) = rule.price_per_tb_max_dollars {
+            if !self.price_per_tb().is_some_and(|p| p <= *price_per_tb_max) {
+                return false;
+            }
+        }
+
+        if let Some(ref price_per_gb_max) = rule.price_per_gb_max_dollars {
+            if !self.price_per_gb().is_some_and(|p| p <= *price_per_gb_max) {
+                return false;
+            }
+        }
+
+        if !rule.conditions.is_match(&self.condition) {
+            return false;
+        }
+
+        if !rule.attribute_filters.iter().all(|filter| filter.is_match(&self.attributes)) {
+            return false;
+        }
+
         true    
     }
 }
 
+#[allow(dead_code)]
 #[derive(FromRow)]
 pub struct Rule {
     pub id: u64,
@@ -116,6 +442,9 @@ pub struct Rule {
     pub description_pattern: Option<String>,
     pub price_min: Option<f64>,
     pub price_max: Option<f64>,
+    pub attribute_filters: Option<String>,
+    pub price_per_tb_max: Option<f64>,
+    pub price_per_gb_max: Option<f64>,
 }
 
 #[cfg(test)]
@@ -131,14 +460,22 @@ mod tests {
             description: "ASUS - NVIDIA GeForce RTX 4070 Ti TUF 12GB GDDR6X PCI Express 4.0 Graphics Card - Black".to_owned(),
             price_dollars: 799,
             price_cents: 99,
-            extra_details: None
+            extra_details: None,
+            attributes: Attributes {
+                chip: Some("RTX 4070 Ti".to_owned()),
+                vram_gb: Some(12),
+                ..Attributes::default()
+            },
+            offer_index: 0,
+            condition: Condition::default(),
+            currency: "USD".to_owned(),
         };
 
         let parsed = Title::parse(title, "1234");
-        assert!(parsed.is_some());
+        assert!(parsed.is_ok());
         let parsed = parsed.unwrap();
 
-        assert_eq!(parsed, expected);
+        assert_eq!(parsed, vec![expected]);
     }
 
     #[test]
@@ -150,14 +487,18 @@ mod tests {
             description: "ASUS TUF GAMING B650M-PLUS WIFI AM5 Ryzen 7000 mATX gaming motherboard(14 power stages, PCIe 5.0 M.2 support, DDR5 memory, 2.5 Gb Ethernet, WiFi 6, USB4 support and Aura Sync)".to_owned(),
             price_dollars: 196,
             price_cents: 0,
-            extra_details: Some("FS".to_owned())
+            extra_details: Some("FS".to_owned()),
+            attributes: Attributes::default(),
+            offer_index: 0,
+            condition: Condition::default(),
+            currency: "USD".to_owned(),
         };
 
         let parsed = Title::parse(title, "1234");
-        assert!(parsed.is_some());
+        assert!(parsed.is_ok());
         let parsed = parsed.unwrap();
 
-        assert_eq!(parsed, expected);
+        assert_eq!(parsed, vec![expected]);
     }
 
     #[test]
@@ -170,12 +511,208 @@ mod tests {
             price_dollars: 163,
             price_cents: 19,
             extra_details: Some("($254.99-$91.80) MICROCENTER IN STORE ONLY".to_owned()),
+            attributes: Attributes {
+                efficiency: Some("Platinum".to_owned()),
+                ..Attributes::default()
+            },
+            offer_index: 0,
+            condition: Condition::default(),
+            currency: "USD".to_owned(),
         };
 
         let parsed = Title::parse(title, "1234");
-        assert!(parsed.is_some());
+        assert!(parsed.is_ok());
         let parsed = parsed.unwrap();
 
-        assert_eq!(parsed, expected);
+        assert_eq!(parsed, vec![expected]);
+    }
+
+    #[test]
+    fn test_parse_title_multiple_offers() {
+        let parsed = Title::parse("[RAM] 2x16GB $89 / 2x32GB $159", "1234").unwrap();
+
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].description, "2x16GB");
+        assert_eq!(parsed[0].price_dollars, 89);
+        assert_eq!(parsed[0].extra_details, None);
+        assert_eq!(parsed[0].attributes.capacity_gb, Some(32.0));
+        assert_eq!(parsed[1].description, "2x32GB");
+        assert_eq!(parsed[1].price_dollars, 159);
+        assert_eq!(parsed[1].offer_index, 1);
+        assert_eq!(parsed[1].attributes.capacity_gb, Some(64.0));
+    }
+
+    #[test]
+    fn test_parse_title_bundle() {
+        let parsed = Title::parse("[CPU] 7800X3D $349 + free game, bundle w/ mobo $499", "1234").unwrap();
+
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].description, "7800X3D");
+        assert_eq!(parsed[0].price_dollars, 349);
+        assert_eq!(parsed[0].extra_details, Some("+ free game".to_owned()));
+        assert_eq!(parsed[1].description, "bundle w/ mobo");
+        assert_eq!(parsed[1].price_dollars, 499);
+        assert_eq!(parsed[1].extra_details, None);
+    }
+
+    #[test]
+    fn test_parse_title_commas_and_or() {
+        let parsed = Title::parse("[GPU] RX 7900 XTX $899, was $999", "1234").unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].price_dollars, 899);
+        assert_eq!(parsed[0].extra_details, Some(", was $999".to_owned()));
+        assert_eq!(parsed[0].original_price(), Some(999.0));
+
+        for title in ["[GPU] RX 7800 XT $449, reg. $549", "[GPU] RX 7800 XT $449 reg $549"] {
+            let parsed = Title::parse(title, "1234").unwrap();
+            assert_eq!(parsed.len(), 1, "{title}");
+            assert_eq!(parsed[0].original_price(), Some(549.0));
+        }
+
+        let parsed = Title::parse("[CPU] Core i5 or i7 $199", "1234").unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].description, "Core i5 or i7");
+
+        let parsed = Title::parse("[Laptop] ThinkPad X1 $1,299.99 (was $1,599.99)", "1234").unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].price_dollars, 1299);
+        assert_eq!(parsed[0].price_cents, 99);
+    }
+
+    #[test]
+    fn test_parse_title_condition() {
+        let parsed = Title::parse("[GPU] Refurbished RTX 3080 10GB $399 (Open Box)", "1234").unwrap().remove(0);
+
+        assert!(parsed.condition.refurbished);
+        assert!(parsed.condition.open_box);
+        assert!(!parsed.condition.used);
+
+        let rule: rule::Rule = toml::from_str("product_type_pattern = \"GPU\"").unwrap();
+        assert!(!rule::Subject::is_match(&parsed, &rule));
+
+        let rule: rule::Rule = toml::from_str("product_type_pattern = \"GPU\"\nrefurbished = \"allow\"").unwrap();
+        assert!(rule::Subject::is_match(&parsed, &rule));
+    }
+
+    #[test]
+    fn test_parse_title_failures() {
+        assert_eq!(
+            Title::parse("PSA: Newegg shuffle is live", "1234"),
+            Err(ParseError::NoPrice)
+        );
+        assert_eq!(
+            Title::parse("Corsair HX1000 80+ Platinum - $163.19", "1234"),
+            Err(ParseError::NoMatch("buildapcsales".to_owned()))
+        );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_unit_prices() {
+        let title = Title::parse("[SSD] Samsung 990 Pro 2TB NVMe M.2 SSD $89.98", "1234").unwrap().remove(0);
+
+        let close = |actual: Option<f64>, expected: f64| actual.is_some_and(|actual| (actual - expected).abs() < 1e-9);
+
+        assert!(close(Some(title.price()), 89.98));
+        assert!(close(title.price_per_tb(), 44.99));
+        assert_eq!(title.unit_price_display(), Some("$44.99/TB".to_owned()));
+
+        let title = Title::parse("[RAM] Corsair Vengeance 32GB (2x16GB) DDR5-6000 CL36 $96", "1234").unwrap().remove(0);
+
+        assert!(close(title.price_per_gb(), 3.0));
+        assert_eq!(title.unit_price_display(), Some("$3.00/GB".to_owned()));
+    }
+
+    #[test]
+    fn test_discounts() {
+        let discount = |title: &str| {
+            let title = Title::parse(title, "1234").unwrap().remove(0);
+            let original = title.original_price();
+            (original, title.discount_percent(original).map(f64::round))
+        };
+
+        assert_eq!(discount("[GPU] RX 7800 XT 16GB $449.99 (was $549.99)"), (Some(549.99), Some(18.0)));
+        assert_eq!(discount("[Case] Fractal North $100 (MSRP: $1,000)"), (Some(1000.0), Some(90.0)));
+        assert_eq!(discount("[PSU] RM850x $99.99 ($30 off)"), (Some(129.99), Some(23.0)));
+        assert_eq!(discount("[RAM] 32GB DDR5 $80 (20% off at checkout)"), (None, Some(20.0)));
+        assert_eq!(discount("[SSD] 990 Pro 2TB $149.99 (reg $139.99)"), (None, None));
+        assert_eq!(discount("[SSD] 990 Pro 2TB $149.99 at Amazon"), (None, None));
+    }
+
+    #[test]
+    fn test_rule_subreddits() {
+        let rule: rule::Rule = toml::from_str("subreddits = [\"bapcsalescanada\"]").unwrap();
+        let mut post = Post {
+            created_utc: 0.0,
+            downs: 0.0,
+            link_flair_text: None,
+            title: "[SSD] Samsung 990 Pro 2TB $189".to_owned(),
+            ups: 0.0,
+            url: String::new(),
+            id: "1234".to_owned(),
+            subreddit: "buildapcsales".to_owned(),
+            permalink: String::new(),
+            selftext: String::new(),
+            author: None,
+            domain: String::new(),
+            is_self: false,
+            over_18: false,
+            stickied: false,
+            num_comments: 0,
+            thumbnail: None,
+            source_kind: source::Kind::Reddit,
+        };
+
+        assert!(!rule::Subject::is_match(&post, &rule));
+        assert_eq!(post.get_comments_url(), "https://www.reddit.com/r/buildapcsales/comments/1234");
+
+        post.subreddit = "BapcSalesCanada".to_owned();
+        post.permalink = "/r/bapcsalescanada/comments/1234/samsung_990_pro/".to_owned();
+
+        assert!(rule::Subject::is_match(&post, &rule));
+        assert_eq!(post.get_comments_url(), "https://www.reddit.com/r/bapcsalescanada/comments/1234/samsung_990_pro/");
+    }
+
+    #[test]
+    fn test_unit_price_rule() {
+        let rule: rule::Rule = toml::from_str("price_per_tb_max_dollars = 45.0").unwrap();
+
+        let cheap = Title::parse("[SSD] Samsung 990 Pro 2TB NVMe M.2 SSD $89.98", "1234").unwrap().remove(0);
+        let expensive = Title::parse("[SSD] Samsung 990 Pro 1TB NVMe M.2 SSD $89.98", "1234").unwrap().remove(0);
+        let unknown = Title::parse("[SSD] Samsung 990 Pro NVMe M.2 SSD $89.98", "1234").unwrap().remove(0);
+
+        assert!(rule::Subject::is_match(&cheap, &rule));
+        assert!(!rule::Subject::is_match(&expensive, &rule));
+        assert!(!rule::Subject::is_match(&unknown, &rule));
+    }
+
+    #[test]
+    fn test_post_details() {
+        let post: Post = serde_json::from_value(serde_json::json!({
+            "created_utc": 0.0,
+            "downs": 0.0,
+            "title": "[Monitor] Price match thread",
+            "ups": 1.0,
+            "url": "https://www.reddit.com/r/buildapcsales/comments/1234/",
+            "id": "1234",
+            "selftext": "Best Buy will price match Amazon this week",
+            "author": "user",
+            "domain": "self.buildapcsales",
+            "is_self": true,
+            "stickied": true,
+            "num_comments": 12,
+            "preview": { "images": [{ "source": { "url": "https://preview.redd.it/a.jpg?width=640&amp;s=abc" } }] },
+        })).unwrap();
+
+        assert_eq!(post.thumbnail.as_deref(), Some("https://preview.redd.it/a.jpg?width=640&s=abc"));
+        assert_eq!(post.num_comments, 12);
+
+        let rule: rule::Rule = toml::from_str("body_pattern = \"\\\"price match\\\"\"").unwrap();
+        assert!(rule::Subject::is_match(&post, &rule));
+
+        let rule: rule::Rule = toml::from_str("body_pattern = \"\\\"price match\\\"\"\nstickied = false").unwrap();
+        assert!(!rule::Subject::is_match(&post, &rule));
+
+        let rule: rule::Rule = toml::from_str("domain_pattern = \"amazon\"").unwrap();
+        assert!(!rule::Subject::is_match(&post, &rule));
+    }
+}
@@ -0,0 +1,319 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{error, models::Title};
+
+pub const DEFAULT_SOURCE: &str = "buildapcsales";
+
+/// Bump whenever a parser's behavior changes, so stored parse failures can be
+/// told apart from ones the current parsers would also produce.
+pub const VERSION: i64 = 1;
+
+#[derive(Debug, PartialEq, Eq, Error)]
+pub enum ParseError {
+    #[error("title doesn't match the {0} format")]
+    NoMatch(String),
+    #[error("no price found")]
+    NoPrice,
+    #[error("invalid price: {0}")]
+    InvalidPrice(String),
+}
+
+/// Turns a post title into one `Title` per offer, or explains why the title isn't in a
+/// format the parser understands.
+pub trait TitleParser: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn parse(&self, title: &str, post_id: &str) -> Result<Vec<Title>, ParseError>;
+}
+
+/// The r/buildapcsales format: `[Type] Description $123.45 extra details`
+pub struct BuildapcsalesParser;
+
+impl TitleParser for BuildapcsalesParser {
+    fn name(&self) -> &'static str {
+        "buildapcsales"
+    }
+
+    fn parse(&self, title: &str, post_id: &str) -> Result<Vec<Title>, ParseError> {
+        Title::parse(title, post_id)
+    }
+}
+
+/// Finds the first dollar amount anywhere in the title. Everything before it
+/// is the description, everything after it is extra details, and a leading
+/// `[Type]` tag is used as the product type if there is one.
+pub struct GenericPriceParser;
+
+static GENERIC_TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*\[(?P<type>[^\]]+)\]").unwrap());
+static GENERIC_PRICE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\$\s?(?P<price>\d[\d,]*(?:\.\d{1,2})?)").unwrap());
+
+impl TitleParser for GenericPriceParser {
+    fn name(&self) -> &'static str {
+        "generic"
+    }
+
+    fn parse(&self, title: &str, post_id: &str) -> Result<Vec<Title>, ParseError> {
+        let (product_type, rest) = match GENERIC_TAG.captures(title) {
+            Some(m) => (m["type"].trim().to_owned(), &title[m[0].len()..]),
+            None => (String::new(), title),
+        };
+
+        let m = GENERIC_PRICE.captures(rest).ok_or(ParseError::NoPrice)?;
+        let (price_dollars, price_cents) = parse_price(&m["price"])
+            .ok_or_else(|| ParseError::InvalidPrice(m["price"].to_owned()))?;
+
+        let whole = m.get(0).unwrap();
+        let before = rest[..whole.start()].trim();
+        let after = rest[whole.end()..].trim();
+
+        let (description, extra_details) = if before.is_empty() {
+            (after.to_owned(), None)
+        } else if after.is_empty() {
+            (before.to_owned(), None)
+        } else {
+            (before.to_owned(), Some(after.to_owned()))
+        };
+
+        Ok(vec![Title::new(post_id, product_type, description, price_dollars, price_cents, extra_details)])
+    }
+}
+
+/// A user-supplied regex. Recognized named capture groups are `type`, `desc`,
+/// `extra`, and either `price` or `price_dollars` with optional `price_cents`.
+pub struct RegexParser {
+    regex: Regex,
+}
+
+impl RegexParser {
+    pub fn new(pattern: &str) -> Result<Self, error::Error> {
+        let regex = Regex::new(pattern)?;
+        let has_group = |name: &str| regex.capture_names().flatten().any(|n| n == name);
+        if !has_group("price") && !has_group("price_dollars") {
+            return Err(error::Error::Other(format!(
+                "title parser pattern needs a `price` or `price_dollars` group: {pattern}"
+            )));
+        }
+
+        Ok(Self { regex })
+    }
+}
+
+impl TitleParser for RegexParser {
+    fn name(&self) -> &'static str {
+        "regex"
+    }
+
+    fn parse(&self, title: &str, post_id: &str) -> Result<Vec<Title>, ParseError> {
+        let m = self.regex
+            .captures(title)
+            .ok_or_else(|| ParseError::NoMatch(self.name().to_owned()))?;
+        let group = |name: &str| {
+            m.name(name)
+                .map(|g| g.as_str().trim().to_owned())
+                .filter(|s| !s.is_empty())
+        };
+
+        let (price_dollars, price_cents) = price_from_captures(&m)
+            .ok_or_else(|| ParseError::InvalidPrice(m[0].to_owned()))?;
+
+        Ok(vec![Title::new(
+            post_id,
+            group("type").unwrap_or_default(),
+            group("desc").unwrap_or_else(|| title.trim().to_owned()),
+            price_dollars,
+            price_cents,
+            group("extra"),
+        )])
+    }
+}
+
+fn price_from_captures(m: &Captures) -> Option<(i32, i8)> {
+    if let Some(price) = m.name("price") {
+        return parse_price(price.as_str());
+    }
+
+    let price_dollars = m.name("price_dollars")?.as_str().replace(',', "").parse().ok()?;
+    let price_cents = match m.name("price_cents") {
+        Some(cents) => parse_cents(cents.as_str())?,
+        None => 0,
+    };
+    Some((price_dollars, price_cents))
+}
+
+/// Parses `1,299.99` into `(1299, 99)`.
+pub fn parse_price(price: &str) -> Option<(i32, i8)> {
+    let price = price.trim().trim_start_matches('$').replace(',', "");
+    match price.split_once('.') {
+        Some((dollars, cents)) => Some((dollars.parse().ok()?, parse_cents(cents)?)),
+        None => Some((price.parse().ok()?, 0)),
+    }
+}
+
+pub fn parse_cents(cents: &str) -> Option<i8> {
+    match cents.len() {
+        1 => cents.parse::<i8>().ok().map(|c| c * 10),
+        2 => cents.parse().ok(),
+        _ => None,
+    }
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Buildapcsales,
+    Generic,
+    Regex,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct Config {
+    pub source: String,
+    pub kind: Kind,
+    pub pattern: Option<String>,
+}
+
+impl Config {
+    fn build(&self) -> Result<Box<dyn TitleParser>, error::Error> {
+        match self.kind {
+            Kind::Buildapcsales => Ok(Box::new(BuildapcsalesParser)),
+            Kind::Generic => Ok(Box::new(GenericPriceParser)),
+            Kind::Regex => {
+                let pattern = self.pattern.as_ref().ok_or_else(|| {
+                    error::Error::Other(format!("regex title parser for {} needs a pattern", self.source))
+                })?;
+                Ok(Box::new(RegexParser::new(pattern)?))
+            }
+        }
+    }
+}
+
+/// Title parsers keyed by source (the subreddit name). Sources without a
+/// registered parser fall back to the buildapcsales format.
+pub struct Registry {
+    parsers: HashMap<String, Box<dyn TitleParser>>,
+    fallback: Box<dyn TitleParser>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        let mut registry = Self {
+            parsers: HashMap::new(),
+            fallback: Box::new(BuildapcsalesParser),
+        };
+        registry.register(DEFAULT_SOURCE, Box::new(BuildapcsalesParser));
+        registry
+    }
+
+    pub fn from_config(configs: &[Config]) -> Result<Self, error::Error> {
+        let mut registry = Self::new();
+        for config in configs {
+            registry.register(&config.source, config.build()?);
+        }
+        Ok(registry)
+    }
+
+    pub fn register(&mut self, source: &str, parser: Box<dyn TitleParser>) {
+        self.parsers.insert(source.to_lowercase(), parser);
+    }
+
+    pub fn get(&self, source: &str) -> &dyn TitleParser {
+        self.parsers
+            .get(&source.to_lowercase())
+            .unwrap_or(&self.fallback)
+            .as_ref()
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_price() {
+        assert_eq!(parse_price("1,299.99"), Some((1299, 99)));
+        assert_eq!(parse_price("$45"), Some((45, 0)));
+        assert_eq!(parse_price("12.5"), Some((12, 50)));
+        assert_eq!(parse_price("abc"), None);
+    }
+
+    #[test]
+    fn test_generic_parser() {
+        let title = GenericPriceParser
+            .parse("Samsung 990 Pro 2TB for $139.99 at Amazon", "1234")
+            .unwrap()
+            .remove(0);
+
+        assert_eq!(title.product_type, "");
+        assert_eq!(title.description, "Samsung 990 Pro 2TB for");
+        assert_eq!(title.price_dollars, 139);
+        assert_eq!(title.price_cents, 99);
+        assert_eq!(title.extra_details, Some("at Amazon".to_owned()));
+    }
+
+    #[test]
+    fn test_generic_parser_leading_price() {
+        let title = GenericPriceParser
+            .parse("[SSD] $89 - Crucial P3 Plus 2TB", "1234")
+            .unwrap()
+            .remove(0);
+
+        assert_eq!(title.product_type, "SSD");
+        assert_eq!(title.description, "- Crucial P3 Plus 2TB");
+        assert_eq!(title.attributes.capacity_gb, Some(2000.0));
+    }
+
+    #[test]
+    fn test_regex_parser() {
+        let parser = RegexParser::new(
+            r"^\[H\] (?P<desc>.+?) \[W\] (?:PayPal|\$)\s?(?P<price>[\d.,]+)",
+        ).unwrap();
+        let title = parser.parse("[H] RTX 3080 FE [W] $450", "1234").unwrap().remove(0);
+
+        assert_eq!(title.description, "RTX 3080 FE");
+        assert_eq!(title.price_dollars, 450);
+    }
+
+    #[test]
+    fn test_regex_parser_no_match() {
+        let parser = RegexParser::new(r"^\[H\] (?P<desc>.+?) \[W\] \$(?P<price>[\d.,]+)").unwrap();
+
+        assert_eq!(
+            parser.parse("[META] Rules update", "1234").map(|t| t.len()),
+            Err(ParseError::NoMatch("regex".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_regex_parser_requires_price() {
+        assert!(RegexParser::new(r"(?P<desc>.+)").is_err());
+    }
+
+    #[test]
+    fn test_registry_from_config() {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            title_parsers: Vec<Config>,
+        }
+
+        let wrapper: Wrapper = toml::from_str(r#"
+[[title_parsers]]
+source = "GameDeals"
+kind = "generic"
+"#).unwrap();
+        let registry = Registry::from_config(&wrapper.title_parsers).unwrap();
+
+        let title = "Hollow Knight is $7.49 on Steam";
+        assert!(registry.get("gamedeals").parse(title, "1234").is_ok());
+        assert_eq!(registry.get("buildapcsales").parse(title, "1234"), Err(ParseError::NoMatch("buildapcsales".to_owned())));
+        assert!(registry.get("unknown").parse(title, "1234").is_err());
+    }
+}
@@ -1,35 +1,99 @@
-use std::{thread, time::Duration};
+use std::{sync::Arc, time::Duration};
 
-use tokio::{sync::mpsc};
+use chrono::Timelike;
+use tokio::{sync::{mpsc, Mutex, MutexGuard}, time::Instant};
 
-use crate::{config, error::Error, rule::{Rules, Rule}, models::{Post, Title}, reddit::{ListingResponse, self, ListingRequest}, db, discord::{self, CreateMessageRequest, Embed}};
+use crate::{comments::{self, Comment, CommentRule}, condition::Condition, config, error::Error, http::{self, HttpClient}, lifecycle::{self, DealStatus}, parser::{self, ParseError}, retry::{self, Backoff}, rule::{Rules, Rule}, models::{Post, Title}, reddit, schedule::Scheduler, db, discord::{self, Color, CreateMessageRequest, Embed, EmbedFooter, EmbedImage, Field, MessageReference}, source::{self, Source, Sources}, velocity::{self, Popularity, PostStats, Snapshot}, webhook};
 
 pub async fn polling_loop(config: config::Config) -> Result<(), Error> {
+    let parsers = Arc::new(parser::Registry::from_config(&config.parser_configs())?);
+    let rules = Arc::new(config.rules);
+    let http = http::ReqwestClient::shared(&config.http)?;
+
     let mut db = db::Client::new(config.db);
     db.connect().await?;
 
-    write_rules(&db, &config.rules).await?;
+    write_rules(&db, &rules).await?;
 
-    // Reddit polling loop
     let (tx_post, mut rx_post) = mpsc::channel(32);
-    tokio::spawn(async {
-        poll_buildapcsales_forever(config.reddit, tx_post).await.unwrap();
-    });
-
-    // Process new posts and pass to notify loop
     let (tx_notify, mut rx_notify) = mpsc::channel(32);
+
+    // Posts pushed over HTTP are stored and matched as they arrive. Polled
+    // posts are handled by the poller, so it knows when to move the cursor.
+    let webhook_task = match config.webhook {
+        Some(webhook) => Some(tokio::spawn(webhook::Listener::bind(webhook).await?.run(tx_post.clone()))),
+        None => None,
+    };
+
+    // Source polling loop
+    let default_wait_time_secs = config.reddit.wait_time_secs;
+    let reddit_client = Arc::new(Mutex::new(reddit::Client::new(config.reddit, http.clone())));
+    let source_clients = config.sources.0
+        .iter()
+        .map(|source| source.build(&reddit_client, &http))
+        .collect::<Result<Vec<_>, _>>()?;
+    let poller = Poller {
+        db: db.clone(),
+        reddit_client,
+        sources: config.sources.clone(),
+        source_clients,
+        default_wait_time_secs,
+        scheduler: Scheduler::new(config.schedule, config.sources.0.len()),
+        rules: rules.clone(),
+        parsers: parsers.clone(),
+        retry: config.retry,
+        lifecycle: config.lifecycle,
+        velocity: config.velocity,
+        comments: config.comments,
+        comment_rules: config.comment_rules,
+        tx_notify: tx_notify.clone(),
+    };
+    let poll_task = tokio::spawn(poller.run());
+
+    // Process posts from the webhook and pass to notify loop
     let tx_notify2 = tx_notify.clone();
-    tokio::spawn(async move {
-        process_posts(db, &mut rx_post, &tx_notify2, &config.rules).await.unwrap();
+    let notify_db = db.clone();
+    let process_task = tokio::spawn(async move {
+        process_posts(db, &mut rx_post, &tx_notify2, &rules, &parsers, &config.sources).await
     });
 
     // Receive matches and notify user in batches
-    notify_loop(config.discord, &mut rx_notify, &tx_notify).await?;
+    let mut alert_client = discord::Client::new(config.discord.clone(), http.clone());
+    let result = tokio::select! {
+        res = poll_task => task_result("Polling", res),
+        res = process_task => task_result("Post processing", res),
+        res = join_optional(webhook_task) => task_result("Webhook", res),
+        res = notify_loop(config.discord, http, notify_db, &mut rx_notify, &tx_notify) => res,
+    };
 
-    Ok(())
+    if let Err(e) = &result {
+        let message = format!("Stopped: {e}");
+        if let Err(alert_error) = send_discord_alert(&mut alert_client, &message).await {
+            log::error!("Failed to send alert: {alert_error}");
+        }
+    }
+
+    result
+}
+
+/// Long-running tasks only return when something has gone wrong.
+fn task_result(name: &str, result: Result<Result<(), Error>, tokio::task::JoinError>) -> Result<(), Error> {
+    match result {
+        Ok(Ok(())) => Err(Error::Other(format!("{name} task stopped"))),
+        Ok(Err(e)) => Err(e),
+        Err(e) => Err(Error::Other(format!("{name} task failed: {e}"))),
+    }
 }
 
-async fn write_rules(db: &db::Client, rules: &Rules) -> Result<(), Error> {
+/// Waits for `task`, or forever if there isn't one.
+async fn join_optional<T>(task: Option<tokio::task::JoinHandle<T>>) -> Result<T, tokio::task::JoinError> {
+    match task {
+        Some(task) => task.await,
+        None => std::future::pending().await,
+    }
+}
+
+pub async fn write_rules(db: &db::Client, rules: &Rules) -> Result<(), Error> {
     for rule in &rules.rules {
         db.insert_rule(rule).await?;
     }
@@ -37,86 +101,415 @@ async fn write_rules(db: &db::Client, rules: &Rules) -> Result<(), Error> {
     Ok(())
 }
 
-async fn write_posts(tx: &mpsc::Sender<Post>, listing: &ListingResponse) -> Result<(), Error> {
-    for response_child in &listing.data.children {
-        let post = &response_child.data;
-        tx.send(post.clone())
-            .await
-            .map_err(|e| Error::Other(e.to_string()))?;
-    }
+/// Work done by the polling task. Everything but polling non-Reddit sources
+/// goes through the shared Reddit client.
+#[derive(Debug, Clone, Copy)]
+enum Job {
+    Poll(usize),
+    CheckDeals,
+    Snapshot,
+    ScanComments,
+}
 
-    Ok(())
+/// A job's next run, with its own failure count so one failing job can't
+/// hide or reset another's.
+struct Slot {
+    job: Job,
+    at: Instant,
+    backoff: Backoff,
 }
 
-async fn poll_buildapcsales_forever(config: reddit::Config, tx: mpsc::Sender<Post>) -> Result<(), Error> {
-    let mut reddit_client = reddit::Client::new(config);
-    reddit_client.read_auth_from_file()?;
-    
-    loop {
-        if reddit_client.is_auth_expired() {
-            reddit_client.authenticate()?;
+/// Polls each source for new posts, plus periodic re-checks of recent Reddit
+/// posts. Reddit work all shares one client, and so one rate limit.
+struct Poller {
+    db: db::Client,
+    reddit_client: Arc<Mutex<reddit::Client>>,
+    sources: Sources,
+    /// One per entry in `sources`
+    source_clients: Vec<Box<dyn Source>>,
+    default_wait_time_secs: u64,
+    scheduler: Scheduler,
+    rules: Arc<Rules>,
+    parsers: Arc<parser::Registry>,
+    retry: retry::Config,
+    lifecycle: lifecycle::Config,
+    velocity: velocity::Config,
+    comments: comments::Config,
+    comment_rules: Vec<CommentRule>,
+    tx_notify: mpsc::Sender<NotifyMessage>,
+}
+
+impl Poller {
+    /// Runs each job on its own interval. Retryable errors back off and try
+    /// again, with an alert once they keep happening; anything else stops
+    /// that job, with an alert, and leaves the others running.
+    async fn run(mut self) -> Result<(), Error> {
+        if self.sources.0.is_empty() {
+            return Err(Error::Other("no sources configured".to_owned()));
+        }
+
+        let now = Instant::now();
+        let mut schedule: Vec<Slot> = (0..self.sources.0.len())
+            .map(|i| self.slot(Job::Poll(i), now))
+            .collect();
+        // Only posts from the Reddit API can be re-checked
+        if self.sources.has_reddit() {
+            self.reddit_client.lock().await.read_auth_from_file().await?;
+
+            schedule.push(self.slot(Job::CheckDeals, now + self.interval(Job::CheckDeals)));
+            schedule.push(self.slot(Job::Snapshot, now + self.interval(Job::Snapshot)));
+            if !self.comment_rules.is_empty() {
+                schedule.push(self.slot(Job::ScanComments, now + self.interval(Job::ScanComments)));
+            }
+        }
+        loop {
+            let Some((i, slot)) = schedule.iter_mut().enumerate().min_by_key(|(_, slot)| slot.at) else {
+                return Err(Error::Other("nothing to schedule".to_owned()));
+            };
+            let job = slot.job;
+            let name = self.name(job);
+
+            let now = Instant::now();
+            if slot.at > now {
+                log::info!("Waiting for {}s", (slot.at - now).as_secs());
+                tokio::time::sleep_until(slot.at).await;
+            }
+
+            // Only Reddit jobs wait for Reddit's rate limit, and the others
+            // carry on in the meantime
+            if self.uses_reddit(job) {
+                let budget = self.reddit_client.lock().await.ratelimit_budget();
+                if let Some(budget) = budget.filter(|budget| budget.remaining == 0 && !budget.reset.is_zero()) {
+                    log::info!("Out of requests, {name} waits {}s for the rate limit to reset", budget.reset.as_secs());
+                    slot.at = Instant::now() + budget.reset;
+                    continue;
+                }
+            }
+
+            let result = match job {
+                Job::Poll(i) => self.poll_source(i).await,
+                Job::CheckDeals => self.check_deals().await,
+                Job::Snapshot => self.snapshot_posts().await,
+                Job::ScanComments => self.scan_comments().await,
+            };
+
+            let backoff = &mut schedule[i].backoff;
+            match result {
+                Ok(()) => {
+                    if backoff.failures() >= backoff.alert_threshold() {
+                        send_alert(&self.tx_notify, format!("{name} recovered")).await?;
+                    }
+                    backoff.succeeded();
+
+                    schedule[i].at = Instant::now() + self.next_interval(job).await;
+                }
+                Err(e) if e.is_retryable() => {
+                    let mut wait = backoff.failed(&e);
+                    if matches!(e, Error::Reauthenticate) && backoff.failures() == 1 {
+                        wait = Duration::ZERO;
+                    }
+                    log::warn!("{name} failed ({} in a row), retrying in {}s: {e}", backoff.failures(), wait.as_secs());
+
+                    if backoff.should_alert() {
+                        send_alert(&self.tx_notify, format!("{name} has failed {} times in a row: {e}", backoff.failures())).await?;
+                    }
+
+                    schedule[i].at = Instant::now() + wait;
+                }
+                // Something that won't fix itself, like a banned subreddit or
+                // a feed that's gone, only stops that job
+                Err(e) => {
+                    send_alert(&self.tx_notify, format!("{name} stopped: {e}")).await?;
+                    schedule.remove(i);
+                }
+            }
         }
+    }
+
+    fn slot(&self, job: Job, at: Instant) -> Slot {
+        Slot { job, at, backoff: Backoff::new(self.retry.clone()) }
+    }
 
-        let listing = reddit_client.listing_new("buildapcsales", &ListingRequest {
-            count: 0,
-            limit: 10
-        })?;
+    fn name(&self, job: Job) -> String {
+        match job {
+            Job::Poll(i) => format!("Polling {}", self.sources.0[i].label()),
+            Job::CheckDeals => "Checking deals".to_owned(),
+            Job::Snapshot => "Snapshotting posts".to_owned(),
+            Job::ScanComments => "Scanning comments".to_owned(),
+        }
+    }
 
-        write_posts(&tx, &listing).await?;
+    fn uses_reddit(&self, job: Job) -> bool {
+        match job {
+            Job::Poll(i) => self.sources.0[i].kind.is_reddit_api(),
+            Job::CheckDeals | Job::Snapshot | Job::ScanComments => true,
+        }
+    }
 
-        let wait = reddit_client.get_wait_time();
-        log::info!("Waiting for {}s", wait.as_secs());
-        thread::sleep(wait);
+    fn interval(&self, job: Job) -> Duration {
+        match job {
+            Job::Poll(i) => Duration::from_secs(self.sources.0[i].wait_time_secs.unwrap_or(self.default_wait_time_secs)),
+            Job::CheckDeals => Duration::from_secs(self.lifecycle.check_interval_secs),
+            Job::Snapshot => Duration::from_secs(self.velocity.snapshot_interval_secs),
+            Job::ScanComments => Duration::from_secs(self.comments.check_interval_secs),
+        }
     }
+
+    /// Reddit sources are polled on an interval that adapts to the rate limit
+    /// budget when `schedule.adaptive` is set, everything else on its fixed
+    /// interval.
+    async fn next_interval(&self, job: Job) -> Duration {
+        match job {
+            Job::Poll(i) if self.scheduler.is_adaptive() && self.uses_reddit(job) => {
+                let budget = self.reddit_client.lock().await.ratelimit_budget();
+                let reddit_sources: Vec<usize> = (0..self.sources.0.len()).filter(|&i| self.uses_reddit(Job::Poll(i))).collect();
+                let interval = self.scheduler.interval(i, &reddit_sources, budget, self.interval(job), chrono::Local::now().hour());
+                log::debug!("Next poll of {} in {:.1}s, budget {budget:?}", self.sources.0[i].label(), interval.as_secs_f64());
+                interval
+            }
+            _ => self.interval(job),
+        }
+    }
+
+    /// The shared Reddit client, authenticated.
+    async fn reddit(&self) -> Result<MutexGuard<'_, reddit::Client>, Error> {
+        let mut reddit_client = self.reddit_client.lock().await;
+        reddit_client.ensure_authenticated().await?;
+        Ok(reddit_client)
+    }
+
+    async fn poll_source(&mut self, i: usize) -> Result<(), Error> {
+        let source = &self.sources.0[i];
+        let source_client = &mut self.source_clients[i];
+        let last_seen = self.db.get_source_cursor(&source.name).await?;
+        let posts = source_client.fetch_new(last_seen.as_ref()).await?;
+        log::info!("Got {} new posts from {}", posts.len(), source.label());
+        self.scheduler.record_poll(i, posts.len());
+
+        if let Some(newest) = posts.last() {
+            let cursor = source_client.cursor(newest);
+            for post in posts {
+                store_post(&self.db, post, &self.tx_notify, &self.rules, &self.parsers, &self.sources).await?;
+            }
+            // Only moved once every post is stored, so posts from a poll that
+            // fails part way through are fetched again
+            self.db.set_source_cursor(&source.name, &cursor).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn check_deals(&self) -> Result<(), Error> {
+        let mut reddit_client = self.reddit().await?;
+        lifecycle::check_deals(&self.db, &mut reddit_client, &self.lifecycle, &self.tx_notify).await
+    }
+
+    /// Re-fetches posts from the last few hours, records their score and
+    /// comment count, and checks rules with velocity filters against them.
+    #[allow(clippy::cast_precision_loss)]
+    async fn snapshot_posts(&self) -> Result<(), Error> {
+        let now = chrono::Utc::now().timestamp() as f64;
+        let since = now - (self.velocity.track_hours * 3600) as f64;
+        let post_ids = self.db.get_recent_post_ids(since).await?;
+        log::info!("Snapshotting {} recent posts", post_ids.len());
+
+        for chunk in post_ids.chunks(100) {
+            let fullnames: Vec<String> = chunk.iter().map(|id| format!("t3_{id}")).collect();
+            let stats: Vec<PostStats> = self.reddit().await?.by_id(&fullnames).await?;
+
+            for stats in stats {
+                let previous = self.db.get_latest_snapshot(&stats.post.id).await?;
+                let snapshot = Snapshot::of(&stats, now);
+                self.db.insert_snapshot(&stats.post.id, &snapshot, stats.post.ups, stats.post.downs).await?;
+
+                let popularity = Popularity::between(previous.as_ref(), &snapshot, stats.post.created_utc);
+                self.check_popularity(stats.post, popularity).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs the comment rules over the newest comments on recently matched
+    /// posts, and sends each new match as a follow-up.
+    #[allow(clippy::cast_precision_loss)]
+    async fn scan_comments(&self) -> Result<(), Error> {
+        let since = chrono::Utc::now().timestamp() as f64 - (self.comments.max_age_hours * 3600) as f64;
+        let tracked = self.db.get_tracked_posts(since).await?;
+        log::info!("Scanning comments on {} matched posts", tracked.len());
+
+        for post in tracked {
+            let post_comments = self.reddit().await?.comments(&post.post_id, self.comments.limit).await?;
+            for (comment, comment_rule) in comments::find_matches(&post_comments, &self.comment_rules) {
+                let rule_name = comment_rule.name();
+                if !self.db.insert_comment_match(&post.post_id, comment, &rule_name).await? {
+                    continue;
+                }
+                log::info!("Comment {} on post {} matches {rule_name}", comment.id, post.post_id);
+
+                self.tx_notify
+                    .send(NotifyMessage::CommentMatch {
+                        post_id: post.post_id.clone(),
+                        title: post.title.clone(),
+                        rule_name,
+                        comment: comment.clone(),
+                    })
+                    .await
+                    .map_err(|e| Error::Other(e.to_string()))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn check_popularity(&self, post: Post, popularity: Popularity) -> Result<(), Error> {
+        let titles = parse_offers(&post, &self.parsers, &self.sources).unwrap_or_default();
+        let Some((matching_rule, offer)) = self.rules.get_matching_velocity_rule(&post, &titles, &popularity) else {
+            return Ok(());
+        };
+        if self.db.has_rule_match(&post.id, &matching_rule).await? {
+            return Ok(());
+        }
+
+        let title = offer.and_then(|i| titles.into_iter().nth(i));
+        self.db.insert_rule_match(&post.id, title.as_ref().map_or(0, |title| title.offer_index), &matching_rule).await?;
+        log::info!("Post {} is blowing up, matches {}", post.id, matching_rule.name());
+
+        self.tx_notify
+            .send(NotifyMessage::Trending(Box::new(TrendingPost {
+                matching_rule,
+                post,
+                title,
+                popularity,
+            })))
+            .await
+            .map_err(|e| Error::Other(e.to_string()))
+    }
+}
+
+async fn send_alert(tx: &mpsc::Sender<NotifyMessage>, message: String) -> Result<(), Error> {
+    log::error!("{message}");
+    tx.send(NotifyMessage::Alert(message))
+        .await
+        .map_err(|e| Error::Other(e.to_string()))
 }
 
 #[derive(Debug)]
 pub struct MatchingPost {
-    matching_rule: Rule,
-    post: Post,
-    title: Title,
+    pub matching_rule: Rule,
+    pub post: Post,
+    pub title: Title,
 }
 
-async fn process_posts(db: db::Client, rx: &mut mpsc::Receiver<Post>, tx: &mpsc::Sender<NotifyMessage>, rules: &Rules) -> Result<(), Error> {
+async fn process_posts(db: db::Client, rx: &mut mpsc::Receiver<Post>, tx: &mpsc::Sender<NotifyMessage>, rules: &Rules, parsers: &parser::Registry, sources: &Sources) -> Result<(), Error> {
     loop {
         while let Some(post) = rx.recv().await {
-            let is_new = db.insert_post(&post).await?;
-            if !is_new {
-                continue;
-            }
-            
-            let Some(title) = Title::parse(&post.title, &post.id) else {
-                continue;
-            };
+            store_post(&db, post, tx, rules, parsers, sources).await?;
+        }
+    }
+}
 
-            let is_new = db.insert_parsed_title(&title).await?;
-            if !is_new {
-                continue;
-            }
+/// Stores a post, if it's new, and sends any matches on to the notify loop.
+async fn store_post(db: &db::Client, post: Post, tx: &mpsc::Sender<NotifyMessage>, rules: &Rules, parsers: &parser::Registry, sources: &Sources) -> Result<(), Error> {
+    let is_new = db.insert_post(&post).await?;
+    if !is_new {
+        return Ok(());
+    }
 
-            let Some(matching_rule) = rules.get_matching_rule(&post, &title) else {
-                continue;
-            };
+    for matching_post in process_post(db, post, rules, parsers, sources).await? {
+        log::info!("Found match, sending to notify loop");
+        tx.send(NotifyMessage::NewMatch(Box::new(matching_post)))
+            .await
+            .map_err(|e| Error::Other(e.to_string()))?;
+    }
 
-            let matching_post = MatchingPost {
-                matching_rule,
-                post,
-                title,
-            };
-            db.insert_rule_match(&matching_post.post, &matching_post.matching_rule).await?;
+    Ok(())
+}
+
+/// Parses a stored post's title and checks each offer in it against the
+/// rules. Parse failures are recorded so they can be reprocessed later, and
+/// cleared once the post parses.
+pub async fn process_post(db: &db::Client, post: Post, rules: &Rules, parsers: &parser::Registry, sources: &Sources) -> Result<Vec<MatchingPost>, Error> {
+    let titles = match parse_offers(&post, parsers, sources) {
+        Ok(titles) => titles,
+        Err(e) => {
+            log::debug!("Failed to parse title {:?}: {e}", post.title);
+            let parser = parsers.get(&post.subreddit);
+            db.insert_parse_failure(&post, parser.name(), parser::VERSION, &e.to_string()).await?;
+            return Ok(Vec::new());
+        }
+    };
+    db.delete_parse_failure(&post.id).await?;
 
-            log::info!("Found match, sending to notify loop");
-            tx.send(NotifyMessage::NewMatch(matching_post))
-                .await
-                .map_err(|e| Error::Other(e.to_string()))?;
+    let mut matches = Vec::new();
+    for title in titles {
+        let is_new = db.insert_parsed_title(&title).await?;
+        if !is_new {
+            continue;
         }
+
+        let Some(matching_rule) = rules.get_matching_rule(&post, &title) else {
+            continue;
+        };
+
+        db.insert_rule_match(&post.id, title.offer_index, &matching_rule).await?;
+
+        matches.push(MatchingPost {
+            matching_rule,
+            post: post.clone(),
+            title,
+        });
+    }
+
+    Ok(matches)
+}
+
+/// Parses a post's title into offers, with condition flags from the flair
+/// and the currency of the post's source.
+fn parse_offers(post: &Post, parsers: &parser::Registry, sources: &Sources) -> Result<Vec<Title>, ParseError> {
+    let mut titles = parsers.get(&post.subreddit).parse(&post.title, &post.id)?;
+
+    let flair_condition = post.link_flair_text.as_deref().map(Condition::detect).unwrap_or_default();
+    let currency = sources.currency(&post.subreddit);
+    for title in &mut titles {
+        title.condition = title.condition.union(flair_condition);
+        currency.clone_into(&mut title.currency);
     }
+
+    Ok(titles)
+}
+
+/// A post that matched a rule with velocity filters.
+#[derive(Debug)]
+pub struct TrendingPost {
+    pub matching_rule: Rule,
+    pub post: Post,
+    /// The offer that matched, if the title could be parsed
+    pub title: Option<Title>,
+    pub popularity: Popularity,
 }
 
 #[derive(Debug)]
 pub enum NotifyMessage {
-    NewMatch(MatchingPost),
+    NewMatch(Box<MatchingPost>),
     TimerFired,
+    /// Sent straight away instead of waiting for the next batch
+    Alert(String),
+    /// A post is getting votes or comments quickly, sent straight away
+    Trending(Box<TrendingPost>),
+    /// A deal that was already sent has expired, sold out or been removed
+    DealStatusChanged {
+        post_id: String,
+        title: String,
+        status: DealStatus,
+    },
+    /// A comment on a deal that was already sent matches a comment rule
+    CommentMatch {
+        post_id: String,
+        title: String,
+        rule_name: String,
+        comment: Comment,
+    },
 }
 
 // async fn timer_task(id: u64, duration: Duration, tx: &mpsc::Sender<NotifyMessage>) -> Result<(), Error> {
@@ -139,9 +532,9 @@ async fn clock(seconds: u64, tx: &mpsc::Sender<NotifyMessage>) -> Result<(), Err
     }
 }
 
-async fn notify_loop(config: discord::Config, rx: &mut mpsc::Receiver<NotifyMessage>, tx: &mpsc::Sender<NotifyMessage>) -> Result<(), Error> {
+async fn notify_loop(config: discord::Config, http: Arc<dyn HttpClient>, db: db::Client, rx: &mut mpsc::Receiver<NotifyMessage>, tx: &mpsc::Sender<NotifyMessage>) -> Result<(), Error> {
     let sending_interval_secs = config.sending_interval_secs;
-    let mut discord_client = discord::Client::new(config);
+    let mut discord_client = discord::Client::new(config, http);
 
     let tx2 = tx.clone();
     tokio::spawn(async move {
@@ -153,12 +546,46 @@ async fn notify_loop(config: discord::Config, rx: &mut mpsc::Receiver<NotifyMess
         log::info!("Received message on notify loop: {msg:?}");
         match msg {
             NotifyMessage::NewMatch(m) => {
-                queued_notifications.push(m);
+                queued_notifications.push(*m);
             },
             NotifyMessage::TimerFired => {
-                if !queued_notifications.is_empty() {
-                    notify(&queued_notifications, &mut discord_client)?;
-                    queued_notifications.clear();
+                if queued_notifications.is_empty() {
+                    continue;
+                }
+                match notify(&mut queued_notifications, &mut discord_client, &db).await {
+                    Ok(()) => {}
+                    Err(e) if e.is_retryable() => {
+                        log::warn!("Failed to send {} matches, retrying next batch: {e}", queued_notifications.len());
+                    }
+                    Err(e) => {
+                        log::error!("Dropping {} matches that failed to send: {e}", queued_notifications.len());
+                        queued_notifications.clear();
+                    }
+                }
+            }
+            NotifyMessage::Alert(message) => {
+                if let Err(e) = send_discord_alert(&mut discord_client, &message).await {
+                    log::error!("Failed to send alert: {e}");
+                }
+            }
+            NotifyMessage::Trending(trending) => {
+                if let Err(e) = notify_trending(&trending, &mut discord_client, &db).await {
+                    log::error!("Failed to send trending post {}: {e}", trending.post.id);
+                }
+            }
+            NotifyMessage::DealStatusChanged { post_id, title, status } => {
+                if let Err(e) = notify_status_change(&post_id, &title, status, &mut discord_client, &db).await {
+                    log::error!("Failed to send status change for {post_id}: {e}");
+                }
+            }
+            NotifyMessage::CommentMatch { post_id, title, rule_name, comment } => {
+                match notify_comment_match(&post_id, &title, &rule_name, &comment, &mut discord_client, &db).await {
+                    Ok(()) => {
+                        if let Err(e) = db.set_comment_match_notified(&comment.id).await {
+                            log::error!("Failed to record comment {} on {post_id} as sent: {e}", comment.id);
+                        }
+                    }
+                    Err(e) => log::error!("Failed to send comment {} on {post_id}, retrying on the next scan: {e}", comment.id),
                 }
             }
         }
@@ -167,31 +594,493 @@ async fn notify_loop(config: discord::Config, rx: &mut mpsc::Receiver<NotifyMess
     Ok(())
 }
 
-fn notify(matches: &Vec<MatchingPost>, discord_client: &mut discord::Client) -> Result<(), Error> {
+/// Sends matches in as many messages as Discord's limits need, in order.
+/// Matches are removed from `matches` once their message is sent, so after a
+/// failure only the unsent ones are left to retry.
+pub async fn notify(matches: &mut Vec<MatchingPost>, discord_client: &mut discord::Client, db: &db::Client) -> Result<(), Error> {
     log::warn!("Sending {} matches", matches.len());
-    let body = matches_to_message_request(matches);
-    log::debug!("{}", serde_json::to_string_pretty(&body)?);
-    discord_client.create_message(&body)?;
+    let requests = matches_to_message_requests(matches);
+    let total = requests.len();
+    for (i, (body, count)) in requests.into_iter().enumerate() {
+        if let Some(wait) = discord_client.ratelimit_wait() {
+            log::info!("Waiting {}s to send message {} of {total}", wait.as_secs(), i + 1);
+            tokio::time::sleep(wait).await;
+        }
+        log::debug!("{}", serde_json::to_string_pretty(&body)?);
+        let message = discord_client.create_message(&body).await?;
+        // The message is out, so a failure to record it mustn't stop the rest
+        // from being sent
+        for m in matches.drain(..count) {
+            if let Err(e) = db.insert_notification(&m.post.id, &message.channel_id, &message.id).await {
+                log::error!("Failed to record the notification for {}: {e}", m.post.id);
+            }
+        }
+    }
     Ok(())
 }
 
-fn matches_to_message_request(matches: &Vec<MatchingPost>) -> CreateMessageRequest {
-    let mut embeds = Vec::new();
+async fn notify_trending(trending: &TrendingPost, discord_client: &mut discord::Client, db: &db::Client) -> Result<(), Error> {
+    let popularity = &trending.popularity;
+    let mut description = format!(
+        "{}\n+{:.0} points/h, +{:.0} comments/h (score {}, {:.0}% upvoted)",
+        trending.post.title,
+        popularity.score_per_hour,
+        popularity.comments_per_hour,
+        popularity.score,
+        popularity.upvote_ratio * 100.0,
+    );
+    if let Some(unit_price) = trending.title.as_ref().and_then(Title::unit_price_display) {
+        description.push('\n');
+        description.push_str(&unit_price);
+    }
+
+    let message = discord_client.create_message(&CreateMessageRequest {
+        content: Some("This deal is blowing up:".to_owned()),
+        embeds: Some(vec![Embed {
+            title: Some(format!("🔥 {}", trending.matching_rule.name())),
+            description: Some(description),
+            url: Some(trending.post.get_comments_url()),
+            thumbnail: trending.post.thumbnail.clone().map(|url| EmbedImage { url }),
+            ..Embed::default()
+        }]),
+        ..CreateMessageRequest::default()
+    }).await?;
+    db.insert_notification(&trending.post.id, &message.channel_id, &message.id).await?;
+    Ok(())
+}
+
+/// Replies to the message a deal was sent in, if it was sent, so the update
+/// shows up next to it.
+async fn notify_status_change(post_id: &str, title: &str, status: DealStatus, discord_client: &mut discord::Client, db: &db::Client) -> Result<(), Error> {
+    let Some((_, message_id)) = db.get_notification(post_id).await? else {
+        return Ok(());
+    };
+
+    let emoji = if status == DealStatus::Active { "✅" } else { "❌" };
+    discord_client.create_message(&CreateMessageRequest {
+        content: Some(format!("{emoji} Deal {status}: {title}")),
+        message_reference: Some(MessageReference::reply_to(message_id)),
+        ..CreateMessageRequest::default()
+    }).await?;
+    Ok(())
+}
+
+/// Replies to the message a deal was sent in with a matching comment
+async fn notify_comment_match(post_id: &str, title: &str, rule_name: &str, comment: &Comment, discord_client: &mut discord::Client, db: &db::Client) -> Result<(), Error> {
+    let Some((_, message_id)) = db.get_notification(post_id).await? else {
+        return Ok(());
+    };
+
+    let author = comment.author.as_deref().unwrap_or("[deleted]");
+    discord_client.create_message(&CreateMessageRequest {
+        content: Some(format!("💬 New comment on {title}")),
+        embeds: Some(vec![Embed {
+            title: Some(rule_name.to_owned()),
+            description: Some(format!("{}\n— u/{author} ({} points)", discord::truncate(&comment.body, 1000), comment.score)),
+            url: Some(comment.get_url()),
+            ..Embed::default()
+        }]),
+        message_reference: Some(MessageReference::reply_to(message_id)),
+    }).await?;
+    Ok(())
+}
+
+async fn send_discord_alert(discord_client: &mut discord::Client, message: &str) -> Result<(), Error> {
+    discord_client.create_message(&CreateMessageRequest {
+        content: Some(format!("⚠️ {message}")),
+        ..CreateMessageRequest::default()
+    }).await?;
+    Ok(())
+}
+
+/// Messages for a batch of matches, each with how many of the matches it
+/// holds.
+fn matches_to_message_requests(matches: &[MatchingPost]) -> Vec<(CreateMessageRequest, usize)> {
+    let chunks = discord::chunk_embeds(matches.iter().map(match_to_embed).collect());
+    let total = chunks.len();
+
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, embeds)| {
+            let content = if total == 1 {
+                format!("Found {} matches:", matches.len())
+            } else {
+                format!("Found {} matches ({}/{total}):", matches.len(), i + 1)
+            };
+            let count = embeds.len();
+            let request = CreateMessageRequest {
+                content: Some(content),
+                embeds: Some(embeds),
+                ..CreateMessageRequest::default()
+            };
+            (request, count)
+        })
+        .collect()
+}
+
+/// Embed colors by discount, for rules without a color of their own.
+const DISCOUNT_COLORS: [(f64, Color); 3] = [(40.0, Color(0x57_f2_87)), (20.0, Color(0xfe_e7_5c)), (0.0, Color(0xe6_7e_22))];
+/// Discord's blurple, when the discount isn't known.
+const DEFAULT_COLOR: Color = Color(0x58_65_f2);
+
+fn match_to_embed(m: &MatchingPost) -> Embed {
+    let post = &m.post;
+    let title = &m.title;
+    let comments_url = post.get_comments_url();
+    // Self posts and feed items only have the one page to link to
+    let deal_url = (!post.is_self && !post.url.is_empty() && post.url != comments_url).then(|| post.url.clone());
+
+    let mut description = String::new();
+    if post.is_self && !post.selftext.is_empty() {
+        description.push_str(&discord::truncate(&post.selftext, 300));
+        description.push_str("\n\n");
+    }
+    match &deal_url {
+        Some(deal_url) => description.push_str(&format!("[Deal]({deal_url}) · [Comments]({comments_url})")),
+        None => description.push_str(&format!("[Comments]({comments_url})")),
+    }
+
+    let symbol = source::currency_symbol(&title.currency);
+    let mut price = format!("{symbol}{:.2}", title.price());
+    if let Some(unit_price) = title.unit_price_display() {
+        price.push_str(&format!(" ({unit_price})"));
+    }
+    let mut fields = vec![Field::inline("Price", price)];
+    let original = title.original_price();
+    let discount = title.discount_percent(original);
+    match (original, discount) {
+        (Some(original), Some(discount)) => fields.push(Field::inline("Was", format!("{symbol}{original:.2} (-{discount:.0}%)"))),
+        (None, Some(discount)) => fields.push(Field::inline("Discount", format!("{discount:.0}% off"))),
+        _ => {}
+    }
+    fields.push(Field::inline("Type", title.product_type.clone()));
+    if let Some(retailer) = retailer(post) {
+        fields.push(Field::inline("Retailer", retailer));
+    }
+    if let Some(flair) = post.link_flair_text.as_deref().filter(|flair| !flair.is_empty()) {
+        fields.push(Field::inline("Flair", flair));
+    }
+    // Only Reddit has votes and comments
+    if post.source_kind.is_reddit_api() {
+        fields.push(Field::inline("Score", format!("{:.0} points · {} comments", post.ups - post.downs, post.num_comments)));
+    }
+    fields.push(Field::inline("Rule", m.matching_rule.name()));
+
+    let color = m.matching_rule.color.unwrap_or_else(|| {
+        discount
+            .and_then(|discount| DISCOUNT_COLORS.iter().find(|(min, _)| discount >= *min))
+            .map_or(DEFAULT_COLOR, |(_, color)| *color)
+    });
+
+    Embed {
+        title: Some(post.title.clone()),
+        description: Some(description),
+        url: Some(deal_url.unwrap_or(comments_url)),
+        timestamp: post_timestamp(post),
+        color: Some(color),
+        footer: post_byline(post).map(|text| EmbedFooter { text }),
+        thumbnail: post.thumbnail.clone().map(|url| EmbedImage { url }),
+        fields,
+    }
+}
+
+/// The site a link post goes to, e.g. `amazon.com`.
+fn retailer(post: &Post) -> Option<String> {
+    let domain = post.domain.strip_prefix("www.").unwrap_or(&post.domain);
+    (!post.is_self && !domain.is_empty()).then(|| domain.to_owned())
+}
 
-    for post in matches {
-        embeds.push(match_to_embed(post));
+/// When the post was made, if the source said.
+#[allow(clippy::cast_possible_truncation)]
+fn post_timestamp(post: &Post) -> Option<String> {
+    if post.created_utc <= 0.0 {
+        return None;
     }
+    chrono::DateTime::from_timestamp(post.created_utc as i64, 0).map(|time| time.to_rfc3339())
+}
 
-    CreateMessageRequest {
-        content: Some(format!("Found {} matches:", matches.len())),
-        embeds: Some(embeds)
+/// Who posted and where, e.g. `u/someone · r/buildapcsales`.
+fn post_byline(post: &Post) -> Option<String> {
+    let mut parts = Vec::new();
+    if let Some(author) = &post.author {
+        parts.push(format!("u/{author}"));
+    }
+    if !post.subreddit.is_empty() {
+        parts.push(match post.source_kind {
+            source::Kind::Reddit => format!("r/{}", post.subreddit),
+            _ => post.subreddit.clone(),
+        });
     }
+    (!parts.is_empty()).then(|| parts.join(" · "))
 }
 
-fn match_to_embed(m: &MatchingPost) -> Embed {
-    Embed { 
-        title: Some(m.matching_rule.name()),
-        description: Some(m.post.title.clone()),
-        url: Some(m.post.get_comments_url()), 
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::testing::{serve, FakeDiscord, FakeReddit, StandInResponse, TempDir};
+
+    /// Config for polling the fakes with a temp database, plus `extra` TOML
+    /// such as `[[sources]]`.
+    async fn test_config(reddit: &FakeReddit, discord: &FakeDiscord, dir: &TempDir, extra: &str) -> config::Config {
+        let config = config::Config::from_toml(&format!(
+            r#"
+            [[rules]]
+            name = "990 Pro"
+            product_type_pattern = "SSD"
+            description_pattern = "990 Pro"
+
+            [[rules]]
+            name = "Cheap 4090s"
+            product_type_pattern = "GPU"
+            description_pattern = "4090"
+
+            {extra}
+
+            [reddit]
+            {reddit}
+
+            [discord]
+            {discord}
+
+            [twilio]
+            api_url = "http://127.0.0.1:9/"
+            api_key = "key"
+            api_key_secret = "secret"
+            account_sid = "sid"
+            phone_number_from = "1"
+            phone_number_to = "2"
+
+            [db]
+            db_url = "{db_url}"
+
+            [retry]
+            initial_backoff_secs = 1
+            max_backoff_secs = 1
+            "#,
+            reddit = reddit.config_toml(&dir.path().join("token.json")),
+            discord = discord.config_toml(),
+            db_url = dir.db_url(),
+        ))
+        .unwrap();
+        db::Client::new(config.db.clone()).setup().await.unwrap();
+        config
+    }
+
+    /// Waits until the fake Discord has received `count` messages.
+    async fn wait_for_messages(discord: &FakeDiscord, task: &tokio::task::JoinHandle<Result<(), Error>>, count: usize) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while discord.messages().len() < count {
+            assert!(Instant::now() < deadline, "only got {:#?}", discord.messages());
+            assert!(!task.is_finished(), "polling stopped");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// Polls the fake Reddit with failures injected, and checks that only the
+    /// matching post gets sent to the fake Discord, once.
+    #[tokio::test]
+    async fn test_polls_and_notifies_matches() {
+        let reddit = FakeReddit::start().await;
+        reddit.listing("/r/buildapcsales/new", include_str!("../tests/fixtures/reddit/buildapcsales_new.json"));
+        let discord = FakeDiscord::start().await;
+        let dir = TempDir::new("poll");
+        let config = test_config(&reddit, &discord, &dir, "").await;
+
+        // Both are retried straight away
+        reddit.fail_next(401);
+        reddit.fail_next(429);
+        let task = tokio::spawn(polling_loop(config));
+        wait_for_messages(&discord, &task, 1).await;
+        // Later polls find nothing new
+        tokio::time::sleep(Duration::from_millis(2500)).await;
+        task.abort();
+
+        let messages = discord.messages();
+        assert_eq!(messages.len(), 1, "{messages:#?}");
+        let embeds = messages[0]["embeds"].as_array().unwrap();
+        assert_eq!(embeds.len(), 1);
+        assert_eq!(embeds[0]["title"], "[SSD] Samsung 990 Pro 2TB NVMe M.2 - $149.99");
+        assert_eq!(embeds[0]["url"], "https://www.amazon.com/dp/B0BHJJ9Y77");
+        assert!(embeds[0]["fields"].as_array().unwrap().contains(&serde_json::json!({ "name": "Rule", "value": "990 Pro", "inline": true })));
+
+        assert_eq!(reddit.tokens_issued(), 2);
+        assert!(reddit.requests().len() >= 4, "{:?}", reddit.requests());
+    }
+
+    /// A subreddit that doesn't exist stops being polled, with an alert, and
+    /// the other sources carry on.
+    #[tokio::test]
+    async fn test_permanent_failure_only_stops_that_source() {
+        let reddit = FakeReddit::start().await;
+        reddit.listing("/r/buildapcsales/new", include_str!("../tests/fixtures/reddit/buildapcsales_new.json"));
+        let discord = FakeDiscord::start().await;
+        let dir = TempDir::new("poll");
+        let sources = r#"
+            [[sources]]
+            name = "doesnotexist"
+
+            [[sources]]
+            name = "buildapcsales"
+        "#;
+        let config = test_config(&reddit, &discord, &dir, sources).await;
+
+        let task = tokio::spawn(polling_loop(config));
+        wait_for_messages(&discord, &task, 2).await;
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        task.abort();
+
+        let messages = discord.messages();
+        assert_eq!(messages.len(), 2, "{messages:#?}");
+        let alert = messages[0]["content"].as_str().unwrap();
+        assert!(alert.starts_with("⚠️ Polling r/doesnotexist stopped: "), "{alert}");
+        assert_eq!(messages[1]["embeds"][0]["title"], "[SSD] Samsung 990 Pro 2TB NVMe M.2 - $149.99");
+
+        let polls = |subreddit: &str| reddit.requests().iter().filter(|request| request.starts_with(&format!("/r/{subreddit}/"))).count();
+        assert_eq!(polls("doesnotexist"), 1);
+        assert!(polls("buildapcsales") >= 2);
+    }
+
+    /// A used-up Reddit rate limit holds back the Reddit jobs, while the feed
+    /// keeps being polled.
+    #[tokio::test]
+    async fn test_ratelimit_only_holds_back_reddit() {
+        let reddit = FakeReddit::start().await;
+        reddit.listing("/r/buildapcsales/new", include_str!("../tests/fixtures/reddit/buildapcsales_new.json"));
+        reddit.exhaust_ratelimit();
+        let feed_polls = Arc::new(AtomicUsize::new(0));
+        let handler_polls = feed_polls.clone();
+        let feed = serve(move |_| {
+            handler_polls.fetch_add(1, Ordering::SeqCst);
+            StandInResponse::ok("application/rss+xml", include_str!("../tests/fixtures/feeds/deals.rss"))
+        })
+        .await;
+        let discord = FakeDiscord::start().await;
+        let dir = TempDir::new("poll");
+        let sources = format!(r#"
+            [[sources]]
+            name = "buildapcsales"
+
+            [[sources]]
+            name = "deals"
+            kind = "rss"
+            url = "{feed}deals.rss"
+            wait_time_secs = 1
+        "#);
+        let config = test_config(&reddit, &discord, &dir, &sources).await;
+
+        let task = tokio::spawn(polling_loop(config));
+        tokio::time::sleep(Duration::from_millis(2500)).await;
+        assert!(!task.is_finished(), "polling stopped");
+        task.abort();
+
+        assert_eq!(reddit.requests(), vec!["/r/buildapcsales/new?limit=100"]);
+        assert!(feed_polls.load(Ordering::SeqCst) >= 2);
+    }
+
+    fn gpu_match(i: usize) -> MatchingPost {
+        let post: Post = serde_json::from_value(serde_json::json!({
+            "id": format!("gpu{i}"),
+            "title": format!("[GPU] Deal {i} $100"),
+            "subreddit": "buildapcsales",
+            "url": "",
+            "created_utc": 1_760_785_200.0,
+            "ups": 1.0,
+            "downs": 0.0,
+            "link_flair_text": null,
+        })).unwrap();
+        let title = Title::parse(&post.title, &post.id).unwrap().remove(0);
+        let rule: Rule = toml::from_str("name = \"GPUs\"").unwrap();
+        MatchingPost { matching_rule: rule, post, title }
+    }
+
+    #[test]
+    fn test_match_to_embed() {
+        let post: Post = serde_json::from_value(serde_json::json!({
+            "id": "1a0e04",
+            "title": "[GPU] Sapphire Pulse RX 7800 XT 16GB $449.99 (was $549.99)",
+            "subreddit": "buildapcsales",
+            "permalink": "/r/buildapcsales/comments/1a0e04/",
+            "url": "https://www.newegg.com/p/N82E16814202436",
+            "domain": "www.newegg.com",
+            "author": "gpuhunter",
+            "created_utc": 1_760_785_200.0,
+            "ups": 18.0,
+            "downs": 0.0,
+            "num_comments": 3,
+            "link_flair_text": "Expired",
+        })).unwrap();
+        let title = Title::parse(&post.title, &post.id).unwrap().remove(0);
+        let rule: Rule = toml::from_str("name = \"GPUs\"\nproduct_type_pattern = \"GPU\"").unwrap();
+        let m = MatchingPost { matching_rule: rule, post, title };
+
+        let embed = serde_json::to_value(match_to_embed(&m)).unwrap();
+        assert_eq!(embed["url"], "https://www.newegg.com/p/N82E16814202436");
+        assert_eq!(embed["description"], "[Deal](https://www.newegg.com/p/N82E16814202436) · [Comments](https://www.reddit.com/r/buildapcsales/comments/1a0e04/)");
+        assert_eq!(embed["timestamp"], "2025-10-18T11:00:00+00:00");
+        assert_eq!(embed["footer"]["text"], "u/gpuhunter · r/buildapcsales");
+        // 18% off
+        assert_eq!(embed["color"], 0x00_e6_7e_22);
+        let fields: Vec<(String, String)> = embed["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|field| (field["name"].as_str().unwrap().to_owned(), field["value"].as_str().unwrap().to_owned()))
+            .collect();
+        assert_eq!(fields, [
+            ("Price", "$449.99"),
+            ("Was", "$549.99 (-18%)"),
+            ("Type", "GPU"),
+            ("Retailer", "newegg.com"),
+            ("Flair", "Expired"),
+            ("Score", "18 points · 3 comments"),
+            ("Rule", "GPUs"),
+        ].map(|(name, value)| (name.to_owned(), value.to_owned())));
+
+        let rule: Rule = toml::from_str("name = \"GPUs\"\ncolor = \"#ff0000\"").unwrap();
+        let m = MatchingPost { matching_rule: rule, ..m };
+        assert_eq!(serde_json::to_value(match_to_embed(&m)).unwrap()["color"], 0x00_ff_00_00);
+        assert!(toml::from_str::<Rule>("color = \"red\"").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_notify_splits_batches() {
+        let discord = FakeDiscord::start().await;
+        let dir = TempDir::new("notify");
+        let mut db = db::Client::new(toml::from_str(&format!("db_url = \"{}\"", dir.db_url())).unwrap());
+        db.setup().await.unwrap();
+        let config: discord::Config = toml::from_str(&discord.config_toml()).unwrap();
+        let mut discord_client = discord::Client::new(config, http::ReqwestClient::shared(&http::Config::default()).unwrap());
+
+        // At most 10 embeds fit in a message
+        let mut matches: Vec<MatchingPost> = (0..12).map(gpu_match).collect();
+        // The first post isn't stored, so its notification can't be recorded
+        for m in &matches[1..] {
+            db.insert_post(&m.post).await.unwrap();
+        }
+        notify(&mut matches, &mut discord_client, &db).await.unwrap();
+        assert!(matches.is_empty());
+
+        let messages = discord.messages();
+        let summary: Vec<(&str, Vec<&str>)> = messages
+            .iter()
+            .map(|message| {
+                let titles = message["embeds"].as_array().unwrap().iter().map(|embed| embed["title"].as_str().unwrap()).collect();
+                (message["content"].as_str().unwrap(), titles)
+            })
+            .collect();
+        assert_eq!(summary.len(), 2);
+        assert_eq!(summary[0].0, "Found 12 matches (1/2):");
+        assert_eq!(summary[0].1.len(), 10);
+        assert_eq!(summary[0].1[0], "[GPU] Deal 0 $100");
+        assert_eq!(summary[1], ("Found 12 matches (2/2):", vec!["[GPU] Deal 10 $100", "[GPU] Deal 11 $100"]));
+
+        // Each match is recorded against the message it was sent in, and a
+        // failure to record one doesn't stop the rest
+        assert!(db.get_notification("gpu0").await.unwrap().is_none());
+        assert_eq!(db.get_notification("gpu9").await.unwrap().unwrap().1, "message-1");
+        assert_eq!(db.get_notification("gpu11").await.unwrap().unwrap().1, "message-2");
     }
 }
@@ -1,13 +1,17 @@
-use std::{fs::{File, self}, io::Write, path::Path};
+use std::{path::Path, sync::Arc, time::{Duration, Instant, SystemTime}};
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, de::{DeserializeOwned, IgnoredAny}};
+use tokio::sync::Mutex;
 use url::Url;
 
-use crate::{error::Error, auth::make_basic_auth_header, models::Post};
+use crate::{comments::{self, Comment}, error::Error, auth::make_basic_auth_header, http::{BoxFuture, HttpClient, Request, Response}, models::Post, schedule::Budget, secret::{self, Cipher, Encrypted, Secret}, source::{Cursor, Source}};
 
 pub struct Client {
     pub config: Config,
     pub auth: Option<Auth>,
+    http: Arc<dyn HttpClient>,
+    /// Encrypts the token file when `token_passphrase` is set
+    cipher: Option<Cipher>,
 }
 
 #[derive(Deserialize, Debug, PartialEq)]
@@ -16,100 +20,261 @@ pub struct Config {
     pub api_host: String,
 
     pub token_file: String,
-
-    pub username: String,
-    pub password: String,
+    /// Encrypt the token file with this passphrase
+    pub token_passphrase: Option<Secret<String>>,
+
+    /// How to get access tokens, defaults to the password grant
+    #[serde(default)]
+    pub grant: Grant,
+    /// Account credentials for the password grant
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
     pub client_id: String,
-    pub client_secret: String,
+    /// Empty for installed apps
+    #[serde(default)]
+    pub client_secret: Secret<String>,
     pub user_agent: String,
+    /// Space separated OAuth scopes to request
+    #[serde(default = "default_scope")]
+    pub scope: String,
+    /// Device id sent with the installed_client grant
+    #[serde(default = "default_device_id")]
+    pub device_id: String,
+    /// Redirect URI registered for the app, used by `auth login`
+    #[serde(default = "default_redirect_uri")]
+    pub redirect_uri: String,
 
     pub wait_time_secs: u64,
+
+    /// Posts requested per listing page, Reddit allows at most 100
+    #[serde(default = "default_page_size")]
+    pub page_size: u64,
+    /// How many pages to go back looking for the last seen post
+    #[serde(default = "default_max_pages")]
+    pub max_pages: u64,
+    /// How many pages to fetch for a source that has never been polled
+    #[serde(default = "default_first_run_pages")]
+    pub first_run_pages: u64,
+}
+
+const fn default_page_size() -> u64 {
+    100
+}
+
+const fn default_max_pages() -> u64 {
+    10
+}
+
+const fn default_first_run_pages() -> u64 {
+    1
+}
+
+fn default_scope() -> String {
+    "read".to_owned()
+}
+
+fn default_device_id() -> String {
+    "DO_NOT_TRACK_THIS_DEVICE".to_owned()
+}
+
+fn default_redirect_uri() -> String {
+    "http://localhost:65010/authorize_callback".to_owned()
+}
+
+/// Refresh access tokens this long before they expire.
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// <https://github.com/reddit-archive/reddit/wiki/OAuth2>
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Grant {
+    /// Script apps acting as the account in `username` and `password`
+    #[default]
+    Password,
+    /// Application-only access for confidential clients, enough to read listings
+    ClientCredentials,
+    /// Application-only access for installed apps without a client secret
+    InstalledClient,
+    /// Acting as the account that approved `auth login`, refreshed with the
+    /// refresh token it saved
+    AuthorizationCode,
 }
 
 #[derive(Deserialize)]
 struct AccessTokenResponse {
-    access_token: String,
+    access_token: Secret<String>,
     expires_in: u64,
+    refresh_token: Option<Secret<String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Auth {
-    access_token: String,
-    expires_at: std::time::SystemTime,
+    access_token: Secret<String>,
+    expires_at: SystemTime,
+    #[serde(default)]
+    refresh_token: Option<Secret<String>>,
 
     ratelimit_used: u64,
     ratelimit_remaining: u64,
-    ratelimit_reset: std::time::Duration,
+    ratelimit_reset: Duration,
+    /// When the counts above were last reported, so the reset time can be
+    /// counted down
+    #[serde(skip)]
+    ratelimit_updated: Option<Instant>,
 }
 
 impl Client {
-    pub const fn new(config: Config) -> Self {
-        Self { config, auth: None }
+    pub fn new(config: Config, http: Arc<dyn HttpClient>) -> Self {
+        Self { config, auth: None, http, cipher: None }
     }
 
+    /// Whether the access token is missing or about to expire.
     pub fn is_auth_expired(&self) -> bool {
-        let now = std::time::SystemTime::now();
+        let now = SystemTime::now() + REFRESH_MARGIN;
         self.auth
             .as_ref()
             .map_or(true, |auth| auth.expires_at < now)
     }
 
-    pub fn get_wait_time(&self) -> std::time::Duration {
-        let wait = std::time::Duration::from_secs(self.config.wait_time_secs);
+    /// How long to wait for the rate limit to reset, if it's been used up.
+    pub fn get_ratelimit_wait(&self) -> Option<Duration> {
+        self.auth
+            .as_ref()
+            .filter(|auth| auth.ratelimit_remaining == 0)
+            .map(|auth| auth.ratelimit_reset)
+    }
 
-        self.auth.as_ref().map_or(wait, |auth| {
-            if auth.ratelimit_remaining == 0 {
-                auth.ratelimit_reset
-            } else {
-                wait
-            }
+    /// Requests left before the rate limit resets, once a response has
+    /// reported them.
+    pub fn ratelimit_budget(&self) -> Option<Budget> {
+        let auth = self.auth.as_ref()?;
+        let updated = auth.ratelimit_updated?;
+        Some(Budget {
+            remaining: auth.ratelimit_remaining,
+            reset: auth.ratelimit_reset.saturating_sub(updated.elapsed()),
         })
     }
 
-    pub fn authenticate(&mut self) -> Result<(), Error> {
-        let username = urlencoding::encode(&self.config.username);
-        let password = urlencoding::encode(&self.config.password);
+    pub async fn ensure_authenticated(&mut self) -> Result<(), Error> {
+        if self.is_auth_expired() {
+            self.authenticate().await?;
+        }
+        Ok(())
+    }
+
+    /// Gets a new access token with the configured grant, or with the saved
+    /// refresh token for the authorization code grant.
+    pub async fn authenticate(&mut self) -> Result<(), Error> {
+        let fields = self.token_request_fields()?;
+        self.request_token(&fields).await
+    }
+
+    /// Exchanges the code from an authorization redirect for an access token
+    /// and refresh token, and saves them to the token file.
+    pub async fn authenticate_with_code(&mut self, code: &str) -> Result<(), Error> {
+        let redirect_uri = self.config.redirect_uri.clone();
+        self.request_token(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &redirect_uri),
+        ]).await
+    }
+
+    /// The page to send the user to for `auth login`.
+    pub fn authorize_url(&self, state: &str) -> Result<Url, Error> {
+        let mut url = Url::parse(&self.config.auth_host)?.join("authorize")?;
+        url.query_pairs_mut()
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("response_type", "code")
+            .append_pair("state", state)
+            .append_pair("redirect_uri", &self.config.redirect_uri)
+            .append_pair("duration", "permanent")
+            .append_pair("scope", &self.config.scope);
+        Ok(url)
+    }
+
+    fn token_request_fields(&self) -> Result<Vec<(&'static str, String)>, Error> {
+        let fields = match self.config.grant {
+            Grant::Password => {
+                let (Some(username), Some(password)) = (&self.config.username, &self.config.password) else {
+                    return Err(Error::Other("the password grant needs reddit.username and reddit.password".to_owned()));
+                };
+                vec![
+                    ("grant_type", "password".to_owned()),
+                    ("username", username.clone()),
+                    ("password", password.expose().clone()),
+                ]
+            }
+            Grant::ClientCredentials => vec![
+                ("grant_type", "client_credentials".to_owned()),
+                ("scope", self.config.scope.clone()),
+            ],
+            Grant::InstalledClient => vec![
+                ("grant_type", "https://oauth.reddit.com/grants/installed_client".to_owned()),
+                ("device_id", self.config.device_id.clone()),
+                ("scope", self.config.scope.clone()),
+            ],
+            Grant::AuthorizationCode => {
+                let Some(refresh_token) = self.auth.as_ref().and_then(|auth| auth.refresh_token.clone()) else {
+                    return Err(Error::Other("no refresh token saved, run `auth login` first".to_owned()));
+                };
+                vec![
+                    ("grant_type", "refresh_token".to_owned()),
+                    ("refresh_token", refresh_token.expose().clone()),
+                ]
+            }
+        };
+
+        Ok(fields)
+    }
 
-        let uri = format!("access_token?grant_type=password&username={username}&password={password}");
-        let auth_url = Url::parse(&self.config.auth_host)?
-            .join(&uri)?;
+    async fn request_token<S: AsRef<str>>(&mut self, fields: &[(&str, S)]) -> Result<(), Error> {
+        let auth_url = Url::parse(&self.config.auth_host)?.join("access_token")?;
+        let fields: Vec<(&str, &str)> = fields.iter().map(|(name, value)| (*name, value.as_ref())).collect();
 
         let auth_payload = self.get_authorization_header();
-        
-        let request_time = std::time::SystemTime::now();
-        let body = ureq::post(auth_url.as_ref())
-            .set("Authorization", &auth_payload)
-            .set("User-Agent", &self.config.user_agent)
-            .call().map_err(|e| Error::Ureq(Box::new(e)))?
-            .into_string()?;
-        let AccessTokenResponse { access_token, expires_in } =
-            serde_json::from_str(&body)?;
-        let expires_at = request_time + std::time::Duration::from_secs(expires_in);
+
+        let request_time = SystemTime::now();
+        let request = Request::post(auth_url)
+            .header("Authorization", &auth_payload)
+            .header("User-Agent", &self.config.user_agent)
+            .form(&fields);
+        let AccessTokenResponse { access_token, expires_in, refresh_token } = self.http
+            .send(request).await?
+            .error_for_status()?
+            .json()?;
+        let expires_at = request_time + Duration::from_secs(expires_in);
+
+        // Refreshing doesn't return a new refresh token, keep using the old one
+        let refresh_token = refresh_token.or_else(|| self.auth.take().and_then(|auth| auth.refresh_token));
 
         self.auth = Some(
             Auth {
                 access_token,
                 expires_at,
+                refresh_token,
                 ratelimit_remaining: 1,
                 ratelimit_used: 0,
-                ratelimit_reset: std::time::Duration::from_secs(3600)
+                ratelimit_reset: Duration::from_secs(3600),
+                ratelimit_updated: None,
             }
         );
 
-        log::info!("Got new auth: {:?}", self.auth);
+        log::info!("Got new access token, expires in {expires_in}s");
+        self.write_auth_to_file().await?;
 
         Ok(())
     }
 
     fn get_authorization_header(&self) -> String {
-        make_basic_auth_header(&self.config.client_id, &self.config.client_secret)
+        make_basic_auth_header(&self.config.client_id, self.config.client_secret.expose())
     }
 
-    fn add_api_headers(&self, req: ureq::Request) -> Result<ureq::Request, Error> {
+    fn add_api_headers(&self, req: Request) -> Result<Request, Error> {
         let auth_payload = self.get_auth_payload()?;
 
-        Ok(req.set("Authorization", &auth_payload)
-                        .set("User-Agent", &self.config.user_agent))
+        Ok(req.header("Authorization", &auth_payload)
+                        .header("User-Agent", &self.config.user_agent))
     }
 
     fn get_api_url(&self, uri: &str) -> Result<Url, Error> {
@@ -117,9 +282,9 @@ impl Client {
         Ok(api_url)
     }
 
-    fn get(&self, uri: &str) -> Result<ureq::Request, Error> {
+    fn get(&self, uri: &str) -> Result<Request, Error> {
         let api_url = self.get_api_url(uri)?;
-        let req = self.add_api_headers(ureq::get(api_url.as_ref()))?;
+        let req = self.add_api_headers(Request::get(api_url))?;
         Ok(req)
     }
 
@@ -128,29 +293,46 @@ impl Client {
             if self.is_auth_expired() {
                 Err(Error::Reauthenticate)
             } else {
-                let access_token = &auth.access_token;
-                Ok(format!("bearer {access_token}"))
+                Ok(format!("bearer {}", auth.access_token.expose()))
             }
         })
     }
 
-    fn write_auth_to_file(&self) -> Result<(), Error> {
+    async fn write_auth_to_file(&mut self) -> Result<(), Error> {
         log::info!("Writing auth to file {}", self.config.token_file);
-        let mut file = File::create(&self.config.token_file)?;
-        let auth = serde_json::to_string(&self.auth)?;
-        file.write_fmt(format_args!("{auth}"))?;
-        Ok(())
+        let mut contents = serde_json::to_vec(&self.auth)?;
+        if let Some(passphrase) = &self.config.token_passphrase {
+            let cipher = match self.cipher.take() {
+                Some(cipher) => cipher,
+                None => Cipher::new(passphrase)?,
+            };
+            contents = serde_json::to_vec(&cipher.seal(&contents)?)?;
+            self.cipher = Some(cipher);
+        }
+        secret::write_private_file(&self.config.token_file, &contents).await
     }
 
-    pub fn read_auth_from_file(&mut self) -> Result<(), Error> {
+    pub async fn read_auth_from_file(&mut self) -> Result<(), Error> {
         log::info!("Reading auth from file {}", self.config.token_file);
         if !Path::new(&self.config.token_file).exists() {
             log::info!("File doesn't exist");
             return Ok(());
         }
-        let contents = fs::read_to_string(&self.config.token_file)?;
-        let auth: Auth = serde_json::from_str(&contents)?;
-        if auth.expires_at > std::time::SystemTime::now() {
+        let contents = tokio::fs::read(&self.config.token_file).await?;
+        let auth: Auth = match serde_json::from_slice::<Encrypted>(&contents) {
+            Ok(encrypted) => {
+                let Some(passphrase) = &self.config.token_passphrase else {
+                    return Err(Error::Other(format!("{} is encrypted but reddit.token_passphrase isn't set", self.config.token_file)));
+                };
+                let cipher = Cipher::with_salt(passphrase, &encrypted.salt()?)?;
+                let auth = serde_json::from_slice(&encrypted.open(&cipher)?)?;
+                self.cipher = Some(cipher);
+                auth
+            }
+            Err(_) => serde_json::from_slice(&contents)?,
+        };
+        // An expired token is still kept for its refresh token
+        if auth.expires_at > SystemTime::now() || auth.refresh_token.is_some() {
             self.auth = Some(auth);
         }
 
@@ -158,25 +340,114 @@ impl Client {
         Ok(())
     }
 
-    pub fn listing_new(
+    pub async fn listing_new(
         &mut self,
-        subreddit: &str,
-        body: &ListingRequest,
+        listing: &Listing,
+        request: &ListingRequest,
     ) -> Result<ListingResponse, Error> {
-        let uri = format!("r/{subreddit}/new");
-        let resp = self
-            .get(&uri)?
-            .send_json(body)
-            .map_err(|err| Error::Ureq(Box::new(err)))?;
+        let req = listing.add_query(request.add_query(self.get(&listing.path())?));
+        let resp = self.http.send(req).await?;
+        if resp.status == 401 {
+            self.expire_auth();
+            return Err(Error::Reauthenticate);
+        }
+        let resp = resp.error_for_status()?;
 
-        self.update_ratelimit_counts(&resp)?;
+        self.update_ratelimit_counts(&resp).await?;
 
-        let resp = serde_json::from_str(&resp.into_string()?)?;
-        Ok(resp)
+        resp.json()
+    }
+
+    /// Fetches posts by fullname from `/by_id`, up to 100 at a time. Deleted
+    /// posts are still returned, removed ones may be missing.
+    pub async fn by_id<T: DeserializeOwned>(&mut self, fullnames: &[String]) -> Result<Vec<T>, Error> {
+        let uri = format!("by_id/{}", fullnames.join(","));
+        let resp = self.http.send(self.get(&uri)?).await?;
+        if resp.status == 401 {
+            self.expire_auth();
+            return Err(Error::Reauthenticate);
+        }
+        let resp = resp.error_for_status()?;
+
+        self.update_ratelimit_counts(&resp).await?;
+
+        let listing: ListingResponse<T> = resp.json()?;
+        Ok(listing.data.children.into_iter().map(|child| child.data).collect())
+    }
+
+    /// Fetches the newest comments on a post, flattened out of their reply
+    /// trees.
+    pub async fn comments(&mut self, post_id: &str, limit: u32) -> Result<Vec<Comment>, Error> {
+        let uri = format!("comments/{post_id}");
+        let req = self.get(&uri)?
+            .query("limit", &limit.to_string())
+            .query("sort", "new");
+        let resp = self.http.send(req).await?;
+        if resp.status == 401 {
+            self.expire_auth();
+            return Err(Error::Reauthenticate);
+        }
+        let resp = resp.error_for_status()?;
+
+        self.update_ratelimit_counts(&resp).await?;
+
+        // The post itself comes first, followed by its comments
+        let (_, comments): (IgnoredAny, comments::Listing) = resp.json()?;
+        Ok(comments.flatten())
+    }
+
+    /// Pages back through a listing until the post with fullname `last_seen`,
+    /// or one older than it, is reached, and returns everything newer than
+    /// it, oldest first.
+    /// Without `last_seen` only `first_run_pages` pages are fetched.
+    pub async fn listing_new_since(
+        &mut self,
+        listing: &Listing,
+        last_seen: Option<&Cursor>,
+    ) -> Result<Vec<Post>, Error> {
+        let max_pages = match last_seen {
+            Some(_) => self.config.max_pages,
+            None => self.config.first_run_pages,
+        };
+
+        let mut posts = Vec::new();
+        let mut after = None;
+        for _ in 0..max_pages {
+            let response = self.listing_new(listing, &ListingRequest {
+                limit: self.config.page_size,
+                after: after.take(),
+            }).await?;
+
+            let (page, found) = take_until_seen(response.data.children, last_seen);
+            posts.extend(page);
+            if found {
+                break;
+            }
+
+            let Some(next) = response.data.after else {
+                break;
+            };
+            after = Some(next);
+        }
+
+        if last_seen.is_some() && after.is_some() {
+            log::warn!("Didn't reach the last seen post in {listing} after {max_pages} pages, some posts may have been missed");
+        }
+
+        posts.reverse();
+        Ok(posts)
+    }
+
+    fn expire_auth(&mut self) {
+        log::warn!("Access token was rejected, reauthenticating");
+        // Keep the refresh token, if there is one
+        if let Some(auth) = self.auth.as_mut() {
+            auth.expires_at = SystemTime::UNIX_EPOCH;
+        }
     }
 
     #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
-    fn update_ratelimit_counts(&mut self, resp: &ureq::Response) -> Result<(), Error> {
+    async fn update_ratelimit_counts(&mut self, resp: &Response) -> Result<(), Error> {
         if let Some(ref mut auth) = self.auth {
             let used: f64 = resp
                 .header("X-Ratelimit-Used")
@@ -195,35 +466,346 @@ impl Client {
 
             auth.ratelimit_remaining = remaining.floor() as u64;
             auth.ratelimit_used = used.floor() as u64;
-            auth.ratelimit_reset = std::time::Duration::from_secs(reset.floor() as u64);
+            auth.ratelimit_reset = Duration::from_secs(reset.floor() as u64);
+            auth.ratelimit_updated = Some(Instant::now());
 
-            self.write_auth_to_file()?;
+            self.write_auth_to_file().await?;
         }
 
         Ok(())
     }
 }
 
-#[derive(Serialize)]
 pub struct ListingRequest {
-    pub count: u64,
     pub limit: u64,
+    pub after: Option<String>,
+}
+
+impl ListingRequest {
+    fn add_query(&self, req: Request) -> Request {
+        let mut req = req.query("limit", &self.limit.to_string());
+        if let Some(after) = &self.after {
+            req = req.query("after", after);
+        }
+        req
+    }
+}
+
+/// Newest-first listings that can be paged back through.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Listing {
+    /// `/r/{subreddit}/new`, which also takes `a+b+c` to combine subreddits
+    Subreddit(String),
+    /// `/user/{user}/m/{name}/new`
+    Multireddit { user: String, name: String },
+    /// `/search` across all of Reddit, sorted by new. Reddit's search
+    /// syntax works, e.g. `subreddit:buildapcsales 4090`.
+    Search(String),
+}
+
+impl Listing {
+    /// Parses a multireddit given as `user/name` or as its path,
+    /// `/user/{user}/m/{name}`.
+    pub fn multireddit(path: &str) -> Result<Self, Error> {
+        let parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+        let (user, name) = match parts.as_slice() {
+            [user, name] | ["user" | "u", user, "m", name] => (user, name),
+            _ => return Err(Error::Other(format!("expected a multireddit like user/name, got {path:?}"))),
+        };
+        Ok(Self::Multireddit {
+            user: (*user).to_owned(),
+            name: (*name).to_owned(),
+        })
+    }
+
+    fn path(&self) -> String {
+        match self {
+            Self::Subreddit(subreddit) => format!("r/{subreddit}/new"),
+            Self::Multireddit { user, name } => format!("user/{user}/m/{name}/new"),
+            Self::Search(_) => "search".to_owned(),
+        }
+    }
+
+    fn add_query(&self, req: Request) -> Request {
+        match self {
+            Self::Search(query) => req.query("q", query).query("sort", "new").query("type", "link"),
+            _ => req,
+        }
+    }
+}
+
+impl std::fmt::Display for Listing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Subreddit(subreddit) => write!(f, "r/{subreddit}"),
+            Self::Multireddit { user, name } => write!(f, "u/{user}/m/{name}"),
+            Self::Search(query) => write!(f, "search {query:?}"),
+        }
+    }
+}
+
+/// A listing as a source of posts. Every listing shares one client, and so
+/// one rate limit.
+pub struct ListingSource {
+    listing: Listing,
+    client: Arc<Mutex<Client>>,
+}
+
+impl ListingSource {
+    pub fn new(listing: Listing, client: Arc<Mutex<Client>>) -> Self {
+        Self { listing, client }
+    }
+}
+
+impl Source for ListingSource {
+    fn fetch_new<'a>(&'a mut self, last_seen: Option<&'a Cursor>) -> BoxFuture<'a, Result<Vec<Post>, Error>> {
+        Box::pin(async move {
+            let mut client = self.client.lock().await;
+            client.ensure_authenticated().await?;
+
+            let mut posts = client.listing_new_since(&self.listing, last_seen).await?;
+            if let Listing::Subreddit(subreddit) = &self.listing {
+                for post in posts.iter_mut().filter(|post| post.subreddit.is_empty()) {
+                    post.subreddit.clone_from(subreddit);
+                }
+            }
+            Ok(posts)
+        })
+    }
+
+    /// Listings page by fullname, and are newest first, so the post's time
+    /// marks where to stop if it's been deleted or dropped out of a search.
+    fn cursor(&self, post: &Post) -> Cursor {
+        Cursor {
+            id: post.fullname(),
+            created_utc: Some(post.created_utc),
+        }
+    }
+}
+
+/// Posts on a listing page up to, but not including, `last_seen`, and whether
+/// the page reached it. A post older than `last_seen` counts as reaching it,
+/// since `last_seen` won't show up once it's deleted.
+fn take_until_seen(children: Vec<ListingResponseChild>, last_seen: Option<&Cursor>) -> (Vec<Post>, bool) {
+    let mut posts = Vec::with_capacity(children.len());
+    for child in children {
+        if let Some(last_seen) = last_seen {
+            let is_older = last_seen.created_utc.is_some_and(|created_utc| child.data.created_utc < created_utc);
+            if is_older || child.data.fullname() == last_seen.id {
+                return (posts, true);
+            }
+        }
+        posts.push(child.data);
+    }
+    (posts, false)
 }
 
 #[derive(Deserialize, Debug)]
-pub struct ListingResponse {
-    pub data: ListingResponseData,
+pub struct ListingResponse<T = Post> {
+    pub data: ListingResponseData<T>,
 }
 
+#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
-pub struct ListingResponseData {
+pub struct ListingResponseData<T = Post> {
     pub after: Option<String>,
     pub before: Option<String>,
-    pub children: Vec<ListingResponseChild>,
+    pub children: Vec<ListingResponseChild<T>>,
 }
 
 #[derive(Deserialize, Debug)]
-pub struct ListingResponseChild {
-    pub data: Post,
+pub struct ListingResponseChild<T = Post> {
+    pub data: T,
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{FakeReddit, TempDir};
+
+    fn cursor(id: &str, created_utc: Option<f64>) -> Cursor {
+        Cursor {
+            id: id.to_owned(),
+            created_utc,
+        }
+    }
+
+    fn child(id: &str) -> ListingResponseChild {
+        ListingResponseChild {
+            data: serde_json::from_value(serde_json::json!({
+                "created_utc": 0.0,
+                "downs": 0.0,
+                "link_flair_text": null,
+                "title": "",
+                "ups": 0.0,
+                "url": "",
+                "id": id,
+            })).unwrap(),
+        }
+    }
+
+    struct NoHttp;
+
+    impl HttpClient for NoHttp {
+        fn send(&self, _request: Request) -> crate::http::BoxFuture<'_, Result<Response, Error>> {
+            Box::pin(async { Err(Error::Other("no network in tests".to_owned())) })
+        }
+    }
+
+    fn client(grant: Grant) -> Client {
+        let config: Config = toml::from_str(r#"
+            auth_host = "https://www.reddit.com/api/v1/"
+            api_host = "https://oauth.reddit.com/"
+            token_file = "token.json"
+            client_id = "id"
+            user_agent = "test"
+            wait_time_secs = 5
+        "#).unwrap();
+        Client::new(Config { grant, ..config }, Arc::new(NoHttp))
+    }
+
+    #[test]
+    fn test_token_request_fields() {
+        let fields = client(Grant::ClientCredentials).token_request_fields().unwrap();
+        assert_eq!(fields, vec![("grant_type", "client_credentials".to_owned()), ("scope", "read".to_owned())]);
+
+        let fields = client(Grant::InstalledClient).token_request_fields().unwrap();
+        assert_eq!(fields[1], ("device_id", "DO_NOT_TRACK_THIS_DEVICE".to_owned()));
+
+        assert!(client(Grant::Password).token_request_fields().is_err());
+        assert!(client(Grant::AuthorizationCode).token_request_fields().is_err());
+
+        let mut with_refresh_token = client(Grant::AuthorizationCode);
+        with_refresh_token.auth = Some(Auth {
+            access_token: "old".into(),
+            expires_at: SystemTime::UNIX_EPOCH,
+            refresh_token: Some("refresh".into()),
+            ratelimit_used: 0,
+            ratelimit_remaining: 0,
+            ratelimit_reset: Duration::ZERO,
+            ratelimit_updated: None,
+        });
+        assert!(with_refresh_token.is_auth_expired());
+        assert_eq!(with_refresh_token.token_request_fields().unwrap()[1], ("refresh_token", "refresh".to_owned()));
+    }
+
+    #[test]
+    fn test_take_until_seen() {
+        let (posts, found) = take_until_seen(vec![child("c"), child("b"), child("a")], Some(&cursor("t3_b", None)));
+        assert!(found);
+        assert_eq!(posts.iter().map(|p| p.id.as_str()).collect::<Vec<_>>(), vec!["c"]);
+
+        let (posts, found) = take_until_seen(vec![child("c"), child("b")], Some(&cursor("t3_a", None)));
+        assert!(!found);
+        assert_eq!(posts.len(), 2);
+
+        let (posts, found) = take_until_seen(vec![child("c")], None);
+        assert!(!found);
+        assert_eq!(posts.len(), 1);
+    }
+
+    #[test]
+    fn test_listing_urls() {
+        let url = |listing: &Listing| {
+            let base = Url::parse("https://oauth.reddit.com/").unwrap();
+            listing.add_query(Request::get(base.join(&listing.path()).unwrap())).url.to_string()
+        };
+
+        assert_eq!(url(&Listing::Subreddit("buildapcsales+hardwareswap".to_owned())), "https://oauth.reddit.com/r/buildapcsales+hardwareswap/new");
+        assert_eq!(url(&Listing::Search("4090 subreddit:buildapcsales".to_owned())), "https://oauth.reddit.com/search?q=4090+subreddit%3Abuildapcsales&sort=new&type=link");
+
+        let multireddit = Listing::multireddit("/user/dealwatcher/m/hardware/").unwrap();
+        assert_eq!(multireddit, Listing::multireddit("dealwatcher/hardware").unwrap());
+        assert_eq!(url(&multireddit), "https://oauth.reddit.com/user/dealwatcher/m/hardware/new");
+        assert_eq!(multireddit.to_string(), "u/dealwatcher/m/hardware");
+        assert!(Listing::multireddit("hardware").is_err());
+    }
+
+    async fn fake_client(fake: &FakeReddit, dir: &TempDir) -> Client {
+        let config: Config = toml::from_str(&fake.config_toml(&dir.path().join("token.json"))).unwrap();
+        let http = crate::http::ReqwestClient::shared(&crate::http::Config::default()).unwrap();
+        let mut client = Client::new(Config { page_size: 2, ..config }, http);
+        client.ensure_authenticated().await.unwrap();
+        client
+    }
+
+    #[tokio::test]
+    async fn test_pages_back_to_last_seen() {
+        let fake = FakeReddit::start().await;
+        fake.listing("/r/buildapcsales/new", include_str!("../tests/fixtures/reddit/buildapcsales_new.json"));
+        let dir = TempDir::new("reddit");
+        let mut client = fake_client(&fake, &dir).await;
+        assert_eq!(fake.tokens_issued(), 1);
+        assert!(dir.path().join("token.json").exists());
+        assert!(client.ratelimit_budget().is_none());
+
+        let listing = Listing::Subreddit("buildapcsales".to_owned());
+        let ids = |posts: Vec<Post>| posts.into_iter().map(|post| post.id).collect::<Vec<_>>();
+
+        // A first run only fetches one page
+        assert_eq!(ids(client.listing_new_since(&listing, None).await.unwrap()), vec!["1a0e04", "1a0e05"]);
+        assert_eq!(ids(client.listing_new_since(&listing, Some(&cursor("t3_1a0e02", None))).await.unwrap()), vec!["1a0e03", "1a0e04", "1a0e05"]);
+        assert_eq!(fake.requests().last().unwrap(), "/r/buildapcsales/new?limit=2&after=t3_1a0e04");
+
+        // A deleted post is passed by time instead, without paging further
+        let requests = fake.requests().len();
+        let deleted = cursor("t3_1a0e02x", Some(1_760_784_700.0));
+        assert_eq!(ids(client.listing_new_since(&listing, Some(&deleted)).await.unwrap()), vec!["1a0e03", "1a0e04", "1a0e05"]);
+        assert_eq!(fake.requests().len(), requests + 2);
+
+        let budget = client.ratelimit_budget().unwrap();
+        assert_eq!(budget.remaining, 595);
+        assert!(budget.reset <= Duration::from_secs(300));
+        assert!(client.get_ratelimit_wait().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_search_falls_back_to_time() {
+        let fake = FakeReddit::start().await;
+        fake.listing("/search", include_str!("../tests/fixtures/reddit/buildapcsales_new.json"));
+        let dir = TempDir::new("reddit");
+        let mut client = fake_client(&fake, &dir).await;
+        let search = Listing::Search("ssd".to_owned());
+
+        // The last post seen no longer matches the search
+        let gone = Cursor {
+            id: "t3_edited".to_owned(),
+            created_utc: Some(1_760_785_000.0),
+        };
+        let posts = client.listing_new_since(&search, Some(&gone)).await.unwrap();
+        assert_eq!(posts.into_iter().map(|post| post.id).collect::<Vec<_>>(), vec!["1a0e04", "1a0e05"]);
+        assert_eq!(fake.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_error_responses() {
+        let fake = FakeReddit::start().await;
+        fake.listing("/r/buildapcsales/new", include_str!("../tests/fixtures/reddit/buildapcsales_new.json"));
+        let dir = TempDir::new("reddit");
+        let mut client = fake_client(&fake, &dir).await;
+        let listing = Listing::Subreddit("buildapcsales".to_owned());
+        let request = ListingRequest { limit: 2, after: None };
+
+        // A rejected token is expired, so the next attempt gets a new one
+        fake.revoke_token();
+        assert!(matches!(client.listing_new(&listing, &request).await, Err(Error::Reauthenticate)));
+        assert!(client.is_auth_expired());
+        client.ensure_authenticated().await.unwrap();
+        assert_eq!(fake.tokens_issued(), 2);
+        assert_eq!(client.listing_new(&listing, &request).await.unwrap().data.children.len(), 2);
+
+        fake.fail_next(429);
+        let error = client.listing_new(&listing, &request).await.unwrap_err();
+        assert!(matches!(error, Error::HttpStatus { status: 429, .. }), "{error}");
+        assert!(error.is_retryable());
+        assert_eq!(error.retry_after(), Some(Duration::ZERO));
+
+        fake.fail_next(503);
+        let error = client.listing_new(&listing, &request).await.unwrap_err();
+        assert!(matches!(error, Error::HttpStatus { status: 503, retry_after: None, .. }), "{error}");
+        assert!(error.is_retryable());
+
+        let missing = Listing::Subreddit("doesnotexist".to_owned());
+        assert!(!client.listing_new(&missing, &request).await.unwrap_err().is_retryable());
+    }
+}
@@ -0,0 +1,73 @@
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::{config, db, discord, error::Error, http, parser, poll::{self, MatchingPost}};
+
+/// Re-runs the current title parsers and rules over stored posts created in
+/// `[since, until)` that never got a parsed title. Matches on posts created at
+/// or after `notify_since` are also sent to Discord.
+pub async fn reprocess(config: config::Config, since: f64, until: f64, notify_since: Option<f64>) -> Result<(), Error> {
+    let parsers = parser::Registry::from_config(&config.parser_configs())?;
+
+    let mut db = db::Client::new(config.db);
+    db.connect().await?;
+
+    poll::write_rules(&db, &config.rules).await?;
+
+    let posts = db.get_unparsed_posts(since, until).await?;
+    log::info!("Reprocessing {} unparsed posts", posts.len());
+
+    let mut matches: Vec<MatchingPost> = Vec::new();
+    for post in posts {
+        for matching_post in poll::process_post(&db, post, &config.rules, &parsers, &config.sources).await? {
+            log::info!("Post {} now matches {}", matching_post.post.id, matching_post.matching_rule.name());
+            if notify_since.is_some_and(|notify_since| matching_post.post.created_utc >= notify_since) {
+                matches.push(matching_post);
+            }
+        }
+    }
+
+    if !matches.is_empty() {
+        let http = http::ReqwestClient::shared(&config.http)?;
+        let mut discord_client = discord::Client::new(config.discord, http);
+        poll::notify(&mut matches, &mut discord_client, &db).await?;
+    }
+
+    Ok(())
+}
+
+/// Parses either an RFC 3339 timestamp or a `YYYY-MM-DD` date (midnight UTC)
+/// into seconds since the epoch.
+pub fn parse_timestamp(s: &str) -> Result<f64, Error> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(s) {
+        return Ok(datetime.timestamp() as f64);
+    }
+
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|e| Error::Other(format!("invalid date {s}: {e}")))?;
+    let datetime = date.and_hms_opt(0, 0, 0).unwrap().and_utc();
+    Ok(datetime.timestamp() as f64)
+}
+
+pub fn now_timestamp() -> f64 {
+    Utc::now().timestamp() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timestamp_date() {
+        assert_eq!(parse_timestamp("2023-02-16").unwrap(), 1676505600.0);
+    }
+
+    #[test]
+    fn test_parse_timestamp_rfc3339() {
+        assert_eq!(parse_timestamp("2023-02-16T04:22:03Z").unwrap(), 1676521323.0);
+    }
+
+    #[test]
+    fn test_parse_timestamp_invalid() {
+        assert!(parse_timestamp("last tuesday").is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::error::Error;
+
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct Config {
+    /// Backoff after the first failure, doubled after each further failure
+    #[serde(default = "default_initial_backoff_secs")]
+    pub initial_backoff_secs: u64,
+    #[serde(default = "default_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+    /// Send an alert once this many polls in a row have failed
+    #[serde(default = "default_alert_after_failures")]
+    pub alert_after_failures: u32,
+}
+
+const fn default_initial_backoff_secs() -> u64 {
+    2
+}
+
+const fn default_max_backoff_secs() -> u64 {
+    600
+}
+
+const fn default_alert_after_failures() -> u32 {
+    5
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            initial_backoff_secs: default_initial_backoff_secs(),
+            max_backoff_secs: default_max_backoff_secs(),
+            alert_after_failures: default_alert_after_failures(),
+        }
+    }
+}
+
+/// Tracks consecutive failures of a repeated operation.
+pub struct Backoff {
+    config: Config,
+    failures: u32,
+}
+
+impl Backoff {
+    pub const fn new(config: Config) -> Self {
+        Self { config, failures: 0 }
+    }
+
+    pub const fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    pub fn succeeded(&mut self) {
+        self.failures = 0;
+    }
+
+    /// Records a failure and returns how long to wait before trying again:
+    /// the server's `Retry-After` if it sent one, up to `max_backoff_secs`,
+    /// otherwise exponential backoff with full jitter.
+    pub fn failed(&mut self, error: &Error) -> Duration {
+        self.failures = self.failures.saturating_add(1);
+
+        let ceiling = self.ceiling();
+        let jittered = Duration::from_secs_f64(rand::thread_rng().gen_range(0.0..=ceiling.as_secs_f64()));
+
+        let max = Duration::from_secs(self.config.max_backoff_secs);
+        error.retry_after().map_or(jittered, |retry_after| retry_after.min(max))
+    }
+
+    pub const fn alert_threshold(&self) -> u32 {
+        self.config.alert_after_failures
+    }
+
+    /// Whether this failure is the one that should trigger an alert.
+    pub const fn should_alert(&self) -> bool {
+        self.failures == self.config.alert_after_failures
+    }
+
+    fn ceiling(&self) -> Duration {
+        let exponent = self.failures.saturating_sub(1).min(32);
+        let secs = self.config.initial_backoff_secs.saturating_mul(1_u64 << exponent);
+        Duration::from_secs(secs.min(self.config.max_backoff_secs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_and_resets() {
+        let mut backoff = Backoff::new(Config::default());
+        let error = Error::Other("boom".to_owned());
+
+        for expected_ceiling in [2, 4, 8, 16, 32] {
+            let wait = backoff.failed(&error);
+            assert!(wait <= Duration::from_secs(expected_ceiling));
+        }
+        assert!(backoff.should_alert());
+
+        for _ in 0..20 {
+            assert!(backoff.failed(&error) <= Duration::from_secs(600));
+        }
+
+        backoff.succeeded();
+        assert_eq!(backoff.failures(), 0);
+    }
+
+    #[test]
+    fn test_backoff_honors_retry_after() {
+        let mut backoff = Backoff::new(Config::default());
+        let error = Error::HttpStatus {
+            status: 429,
+            retry_after: Some(Duration::from_secs(30)),
+            body: String::new(),
+        };
+
+        assert_eq!(backoff.failed(&error), Duration::from_secs(30));
+
+        let error = Error::HttpStatus {
+            status: 503,
+            retry_after: Some(Duration::from_secs(86_400)),
+            body: String::new(),
+        };
+        assert_eq!(backoff.failed(&error), Duration::from_secs(600));
+    }
+}
@@ -6,7 +6,7 @@ use serde_json::Value;
 use sha2::Digest;
 use thiserror::Error;
 
-use crate::models::{Post, Title};
+use crate::{attributes::AttributeFilter, condition::ConditionFilters, discord::Color, models::{Post, Title}, velocity::{Popularity, VelocityFilter}};
 
 #[derive(Deserialize, PartialEq, Default, Debug)]
 pub struct Rules {
@@ -14,6 +14,7 @@ pub struct Rules {
 }
 
 impl Rules {
+    #[allow(dead_code)]
     pub fn read_from_file(filename: &str) -> Result<Self, crate::error::Error> {
         let contents = fs::read_to_string(filename)?;
         let contents: Value = serde_json::from_str(&contents)?;
@@ -31,8 +32,10 @@ impl Rules {
         })
     }
 
+    /// The first rule matching a new post. Rules with velocity filters are
+    /// skipped, since a new post has no votes yet.
     pub fn get_matching_rule(&self, post: &Post, title: &Title) -> Option<Rule> {
-        for rule in self.rules.iter() {
+        for rule in self.rules.iter().filter(|rule| rule.velocity_filters.is_empty()) {
             if post.is_match(&rule) && title.is_match(&rule) {
                 return Some(rule.clone());
             }
@@ -40,6 +43,26 @@ impl Rules {
 
         None
     }
+
+    /// The first rule with velocity filters matching a post's popularity,
+    /// along with the offer it matched. Rules that only look at the post and
+    /// its popularity also match posts whose title couldn't be parsed.
+    pub fn get_matching_velocity_rule(&self, post: &Post, titles: &[Title], popularity: &Popularity) -> Option<(Rule, Option<usize>)> {
+        for rule in self.rules.iter().filter(|rule| !rule.velocity_filters.is_empty()) {
+            if !post.is_match(rule) || !rule.velocity_filters.iter().all(|filter| filter.is_match(popularity)) {
+                continue;
+            }
+
+            if let Some(i) = titles.iter().position(|title| title.is_match(rule)) {
+                return Some((rule.clone(), Some(i)));
+            }
+            if titles.is_empty() && !rule.has_title_predicates() {
+                return Some((rule.clone(), None));
+            }
+        }
+
+        None
+    }
 }
 
 #[derive(PartialEq, Debug, Clone, Deserialize)]
@@ -50,12 +73,39 @@ pub struct Rule {
     pub description_pattern: Option<PatternAndSource>,
     pub price_min_dollars: Option<i64>,
     pub price_max_dollars: Option<i64>,
+    pub price_per_tb_max_dollars: Option<f64>,
+    pub price_per_gb_max_dollars: Option<f64>,
+    #[serde(default)]
+    pub attribute_filters: Vec<AttributeFilter>,
+    #[serde(flatten)]
+    pub conditions: ConditionFilters,
+    /// Only match posts from these subreddits, or from any if empty
+    #[serde(default)]
+    pub subreddits: Vec<String>,
+    /// Filters on a post's score and comment rates, e.g. `score_velocity > 20/h`
+    #[serde(default)]
+    pub velocity_filters: Vec<VelocityFilter>,
+    /// Matched against the body of self posts
+    pub body_pattern: Option<PatternAndSource>,
+    /// Matched against the domain of the linked page
+    pub domain_pattern: Option<PatternAndSource>,
+    pub author_pattern: Option<PatternAndSource>,
+    /// Only match self posts (`true`) or link posts (`false`)
+    pub is_self: Option<bool>,
+    /// Only match NSFW posts (`true`) or other posts (`false`)
+    pub over_18: Option<bool>,
+    /// Only match stickied posts (`true`) or other posts (`false`)
+    pub stickied: Option<bool>,
+    /// Color of this rule's notifications, otherwise they're colored by
+    /// discount. Changing it doesn't change the rule's hash.
+    pub color: Option<Color>,
 }
 
 pub trait Subject {
     fn is_match(&self, rule: &Rule) -> bool;
 }
 
+#[allow(dead_code)]
 impl Rule {
     const fn new() -> Self {
         Self {
@@ -65,7 +115,31 @@ impl Rule {
             description_pattern: None,
             price_max_dollars: None,
             price_min_dollars: None,
-        }
+            price_per_tb_max_dollars: None,
+            price_per_gb_max_dollars: None,
+            attribute_filters: Vec::new(),
+            conditions: ConditionFilters::new(),
+            subreddits: Vec::new(),
+            velocity_filters: Vec::new(),
+            body_pattern: None,
+            domain_pattern: None,
+            author_pattern: None,
+            is_self: None,
+            over_18: None,
+            stickied: None,
+            color: None,
+        }
+    }
+
+    /// Whether the rule looks at anything parsed out of the title.
+    pub fn has_title_predicates(&self) -> bool {
+        self.product_type_pattern.is_some()
+            || self.description_pattern.is_some()
+            || self.price_min_dollars.is_some()
+            || self.price_max_dollars.is_some()
+            || self.price_per_tb_max_dollars.is_some()
+            || self.price_per_gb_max_dollars.is_some()
+            || !self.attribute_filters.is_empty()
     }
 
     pub fn name(&self) -> String {
@@ -163,6 +237,43 @@ impl Rule {
             let payload: &[u8] = bytemuck::bytes_of(&price_max_dollars);
 	        hasher.update(payload);
         }
+        if let Some(price_per_tb_max_dollars) = self.price_per_tb_max_dollars {
+            hasher.update("price_per_tb");
+            hasher.update(bytemuck::bytes_of(&price_per_tb_max_dollars));
+        }
+        if let Some(price_per_gb_max_dollars) = self.price_per_gb_max_dollars {
+            hasher.update("price_per_gb");
+            hasher.update(bytemuck::bytes_of(&price_per_gb_max_dollars));
+        }
+        for attribute_filter in &self.attribute_filters {
+            hasher.update(&attribute_filter.source);
+        }
+        if !self.conditions.is_empty() {
+            hasher.update(format!("{:?}", self.conditions));
+        }
+        for subreddit in &self.subreddits {
+            hasher.update(subreddit.to_lowercase());
+        }
+        for velocity_filter in &self.velocity_filters {
+            hasher.update(&velocity_filter.source);
+        }
+        if let Some(body_pattern) = &self.body_pattern {
+            hasher.update("body");
+            hasher.update(body_pattern.pattern.hash());
+        }
+        if let Some(domain_pattern) = &self.domain_pattern {
+            hasher.update("domain");
+            hasher.update(domain_pattern.pattern.hash());
+        }
+        if let Some(author_pattern) = &self.author_pattern {
+            hasher.update("author");
+            hasher.update(author_pattern.pattern.hash());
+        }
+        for (flag, value) in [("is_self", self.is_self), ("over_18", self.over_18), ("stickied", self.stickied)] {
+            if let Some(value) = value {
+                hasher.update(format!("{flag}={value}"));
+            }
+        }
         
         base64::engine::general_purpose::STANDARD.encode(hasher.finalize().to_vec())
     }
@@ -323,8 +434,10 @@ pub enum Error {
     #[error("column {0}: can't rewind token because it's null")]
     CantRewindToken(usize),
 
+    #[allow(dead_code)]
     #[error("not a json object")]
     NotAnObject,
+    #[allow(dead_code)]
     #[error("wrong type at key {0}")]
     BadValue(String),
 }
@@ -367,6 +480,7 @@ impl Display for MaybeChar {
     }
 }
 
+#[allow(dead_code)]
 fn parse_pattern(input: &str) -> Result<Pattern, Error> {
     let mut scanner = Scanner::new(input);
     scanner.pattern()
@@ -779,8 +893,28 @@ mod tests {
                     pattern: Pattern::Exact("nvidia".to_owned())
                 }),
                 price_min_dollars: None,
-                price_max_dollars: Some(1500)
+                price_max_dollars: Some(1500),
+                price_per_tb_max_dollars: None,
+                price_per_gb_max_dollars: None,
+                attribute_filters: vec![],
+                conditions: ConditionFilters::new(),
+                subreddits: vec![],
+                velocity_filters: vec![],
+                body_pattern: None,
+                domain_pattern: None,
+                author_pattern: None,
+                is_self: None,
+                over_18: None,
+                stickied: None,
+                color: None,
             }
         )
     }
+
+    #[test]
+    fn test_hash_tells_unit_prices_apart() {
+        let per_tb: Rule = toml::from_str("price_per_tb_max_dollars = 45.0").unwrap();
+        let per_gb: Rule = toml::from_str("price_per_gb_max_dollars = 45.0").unwrap();
+        assert_ne!(per_tb.hash(), per_gb.hash());
+    }
 }
@@ -0,0 +1,312 @@
+use std::{fmt, time::Duration};
+
+use serde::{Deserialize, Deserializer};
+
+/// Adaptive polling for Reddit sources. When it's off, each source is polled
+/// every `wait_time_secs`.
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct Config {
+    #[serde(default)]
+    pub adaptive: bool,
+    #[serde(default = "default_min_interval_secs")]
+    pub min_interval_secs: u64,
+    #[serde(default = "default_max_interval_secs")]
+    pub max_interval_secs: u64,
+    /// Share of the remaining requests left for deal checks, snapshots and
+    /// comment scans
+    #[serde(default = "default_reserve_fraction")]
+    pub reserve_fraction: f64,
+    /// How much larger a share of the budget a subreddit with new posts on
+    /// every poll gets than a quiet one
+    #[serde(default = "default_activity_boost")]
+    pub activity_boost: f64,
+    /// Local hours, like "17-23", when intervals are scaled by `peak_factor`
+    #[serde(default)]
+    pub peak_hours: Vec<HourRange>,
+    #[serde(default = "default_peak_factor")]
+    pub peak_factor: f64,
+    /// Local hours, like "1-7", when intervals are scaled by `quiet_factor`
+    #[serde(default)]
+    pub quiet_hours: Vec<HourRange>,
+    #[serde(default = "default_quiet_factor")]
+    pub quiet_factor: f64,
+}
+
+const fn default_min_interval_secs() -> u64 {
+    2
+}
+
+const fn default_max_interval_secs() -> u64 {
+    300
+}
+
+const fn default_reserve_fraction() -> f64 {
+    0.25
+}
+
+const fn default_activity_boost() -> f64 {
+    2.0
+}
+
+const fn default_peak_factor() -> f64 {
+    0.5
+}
+
+const fn default_quiet_factor() -> f64 {
+    4.0
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            adaptive: false,
+            min_interval_secs: default_min_interval_secs(),
+            max_interval_secs: default_max_interval_secs(),
+            reserve_fraction: default_reserve_fraction(),
+            activity_boost: default_activity_boost(),
+            peak_hours: Vec::new(),
+            peak_factor: default_peak_factor(),
+            quiet_hours: Vec::new(),
+            quiet_factor: default_quiet_factor(),
+        }
+    }
+}
+
+/// Hours `start` up to but not including `end`, wrapping past midnight if
+/// `end` is earlier.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct HourRange {
+    start: u32,
+    end: u32,
+}
+
+impl HourRange {
+    pub const fn contains(&self, hour: u32) -> bool {
+        if self.start <= self.end {
+            self.start <= hour && hour < self.end
+        } else {
+            hour >= self.start || hour < self.end
+        }
+    }
+}
+
+impl std::str::FromStr for HourRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hour = |h: &str| h.trim().parse::<u32>().ok().filter(|h| *h <= 24);
+        match s.split_once('-').map(|(start, end)| (hour(start), hour(end))) {
+            // 24 is midnight, the same hour as 0, so "0-24" would be empty
+            Some((Some(start), Some(end))) if start % 24 == end % 24 => Err(format!("{s:?} starts and ends at the same hour")),
+            Some((Some(start), Some(end))) => Ok(Self { start: start % 24, end: end % 24 }),
+            _ => Err(format!("expected hours like \"17-23\", got {s:?}")),
+        }
+    }
+}
+
+impl fmt::Display for HourRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+impl<'de> Deserialize<'de> for HourRange {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Requests left in the current rate limit window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Budget {
+    pub remaining: u64,
+    pub reset: Duration,
+}
+
+/// Picks the interval between polls of each Reddit source. The remaining
+/// budget, less the reserve, is spread evenly over the time left until the
+/// rate limit resets and split between sources by how active they've been.
+/// The result is then scaled for the time of day, with off-peak hours slowed
+/// down to pay for the faster polling at peak hours. Since it's worked out
+/// again after every poll with the latest counts, polling slows down as the
+/// budget runs low instead of running out.
+pub struct Scheduler {
+    config: Config,
+    /// Moving average of new posts per poll, per source
+    activity: Vec<f64>,
+    /// What intervals are scaled by at each hour of the day
+    time_factors: [f64; 24],
+}
+
+/// Weight of the latest poll in the activity average.
+const ACTIVITY_SMOOTHING: f64 = 0.3;
+
+impl Scheduler {
+    pub fn new(config: Config, sources: usize) -> Self {
+        Self {
+            time_factors: time_factors(&config),
+            config,
+            activity: vec![0.0; sources],
+        }
+    }
+
+    pub const fn is_adaptive(&self) -> bool {
+        self.config.adaptive
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn record_poll(&mut self, source: usize, new_posts: usize) {
+        let activity = &mut self.activity[source];
+        *activity += ACTIVITY_SMOOTHING * (new_posts as f64 - *activity);
+    }
+
+    /// Relative share of the budget, from 1 for a quiet source up to
+    /// `activity_boost` for one with a new post on every poll.
+    fn weight(&self, source: usize) -> f64 {
+        1.0 + (self.config.activity_boost - 1.0).max(0.0) * self.activity[source].min(1.0)
+    }
+
+    fn time_factor(&self, hour: u32) -> f64 {
+        self.time_factors[hour as usize % 24]
+    }
+
+    /// How long to wait before polling `source` again, where `sources` are all
+    /// the Reddit sources sharing the budget. Until a response has reported
+    /// the rate limit, `fallback` is used, still scaled for the time of day.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn interval(&self, source: usize, sources: &[usize], budget: Option<Budget>, fallback: Duration, hour: u32) -> Duration {
+        let base = match budget {
+            Some(budget) => {
+                let usable = (budget.remaining as f64 * (1.0 - self.config.reserve_fraction)).max(1.0);
+                let total_weight: f64 = sources.iter().map(|&i| self.weight(i)).sum();
+                let share = self.weight(source) / total_weight.max(f64::MIN_POSITIVE);
+                budget.reset.as_secs_f64() / (usable * share)
+            }
+            None => fallback.as_secs_f64(),
+        };
+
+        let secs = (base * self.time_factor(hour))
+            .clamp(self.config.min_interval_secs as f64, self.config.max_interval_secs.max(self.config.min_interval_secs) as f64);
+        Duration::from_secs_f64(secs)
+    }
+}
+
+/// Interval factors for each hour of the day. Peak hours poll faster than
+/// the budget allows, so if quiet hours don't make up for it, the other hours
+/// are slowed down until a whole day takes no more requests than polling at
+/// the base interval throughout.
+#[allow(clippy::cast_precision_loss)]
+fn time_factors(config: &Config) -> [f64; 24] {
+    let is_peak = |hour: u32| config.peak_hours.iter().any(|range| range.contains(hour));
+    let is_quiet = |hour: u32| config.quiet_hours.iter().any(|range| range.contains(hour));
+    let peak_hours = (0..24).filter(|&hour| is_peak(hour)).count() as f64;
+    // Peak hours can't take more than the whole day's requests on their own
+    let peak_factor = config.peak_factor.max(peak_hours / 24.0);
+
+    let mut factors = [1.0; 24];
+    for (hour, factor) in (0..24).zip(&mut factors) {
+        if is_peak(hour) {
+            *factor = peak_factor;
+        } else if is_quiet(hour) {
+            *factor = config.quiet_factor;
+        }
+    }
+
+    // Requests in a day, in hours' worth at the base interval
+    let peak_requests = if peak_hours > 0.0 { peak_hours / peak_factor } else { 0.0 };
+    let off_peak_requests: f64 = (0..24).filter(|&hour| !is_peak(hour)).map(|hour| 1.0 / factors[hour as usize]).sum();
+    if peak_requests + off_peak_requests > 24.0 {
+        let stretch = off_peak_requests / (24.0 - peak_requests);
+        for (hour, factor) in (0..24).zip(&mut factors) {
+            if !is_peak(hour) {
+                *factor *= stretch;
+            }
+        }
+    }
+    factors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        toml::from_str(r#"
+            adaptive = true
+            min_interval_secs = 1
+            max_interval_secs = 600
+            peak_hours = ["17-23"]
+            quiet_hours = ["23-7"]
+        "#).unwrap()
+    }
+
+    #[test]
+    fn test_hour_ranges() {
+        let range: HourRange = "22-6".parse().unwrap();
+        assert!(range.contains(23));
+        assert!(range.contains(0));
+        assert!(!range.contains(6));
+        assert!(!range.contains(12));
+        assert_eq!(range.to_string(), "22-6");
+        assert!("9".parse::<HourRange>().is_err());
+        assert!("9-25".parse::<HourRange>().is_err());
+        assert!("0-24".parse::<HourRange>().is_err());
+        assert!("9-9".parse::<HourRange>().is_err());
+
+        let range: HourRange = "20-24".parse().unwrap();
+        assert!(range.contains(23));
+        assert!(!range.contains(0));
+    }
+
+    #[test]
+    fn test_spreads_budget_across_sources() {
+        let mut scheduler = Scheduler::new(config(), 3);
+        let budget = Some(Budget { remaining: 400, reset: Duration::from_secs(300) });
+        let fallback = Duration::from_secs(5);
+
+        // 300 usable requests over 300s, split evenly between two subreddits
+        assert_eq!(scheduler.interval(0, &[0, 1], budget, fallback, 12), Duration::from_secs(2));
+
+        // A busy subreddit gets twice the share of a quiet one, and the total
+        // rate stays the same
+        for _ in 0..20 {
+            scheduler.record_poll(0, 3);
+            scheduler.record_poll(1, 0);
+        }
+        let busy = scheduler.interval(0, &[0, 1], budget, fallback, 12).as_secs_f64();
+        let quiet = scheduler.interval(1, &[0, 1], budget, fallback, 12).as_secs_f64();
+        assert!((busy - 1.5).abs() < 0.01, "{busy}");
+        assert!((quiet - 3.0).abs() < 0.01, "{quiet}");
+        assert!((1.0 / busy + 1.0 / quiet - 1.0).abs() < 0.01);
+
+        // Faster at peak times, slower overnight
+        assert_eq!(scheduler.interval(1, &[0, 1], budget, fallback, 18).as_secs(), 1);
+        assert_eq!(scheduler.interval(1, &[0, 1], budget, fallback, 3).as_secs(), 12);
+
+        // Slows down as the budget runs out, within the configured bounds
+        let low = Some(Budget { remaining: 1, reset: Duration::from_secs(300) });
+        assert_eq!(scheduler.interval(1, &[0, 1], low, fallback, 12), Duration::from_secs(600));
+        assert_eq!(scheduler.interval(2, &[2], None, fallback, 12), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_peak_hours_stay_within_budget() {
+        // Quiet hours save more than peak hours spend
+        let factors = time_factors(&config());
+        for (hour, expected) in [(18, 0.5), (12, 1.0), (3, 4.0)] {
+            assert!((factors[hour] - expected).abs() < 1e-9, "{hour}: {}", factors[hour]);
+        }
+
+        // Without them the rest of the day pays for the peak
+        let peak_only: Config = toml::from_str(r#"peak_hours = ["17-23"]"#).unwrap();
+        let factors = time_factors(&peak_only);
+        assert!((factors[18] - 0.5).abs() < 1e-9, "{}", factors[18]);
+        assert!((factors[12] - 1.5).abs() < 1e-9, "{}", factors[12]);
+        let requests: f64 = factors.iter().map(|factor| 1.0 / factor).sum();
+        assert!((requests - 24.0).abs() < 1e-9, "{requests}");
+
+        // A peak all day is no faster than the budget
+        let always: Config = toml::from_str(r#"peak_hours = ["0-12", "12-0"]"#).unwrap();
+        assert!(time_factors(&always).iter().all(|factor| (factor - 1.0).abs() < 1e-9));
+    }
+}
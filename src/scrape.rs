@@ -0,0 +1,211 @@
+use std::sync::{Arc, LazyLock};
+
+use regex::Regex;
+use scraper::{ElementRef, Html, Selector};
+use serde::Deserialize;
+use url::Url;
+
+use crate::{error::Error, feed::{self, Item}, http::{BoxFuture, HttpClient}, models::Post, parser, source::{Cursor, Kind, Source}};
+
+/// CSS selectors for pulling items out of a retailer's listing page. Every
+/// selector but `container` is matched within each container.
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct Selectors {
+    /// One element per item, e.g. `li.product`
+    pub container: String,
+    pub title: String,
+    /// Text with the price in it. The first number is used, so labels like
+    /// "Now $1,299.99" are fine.
+    pub price: String,
+    /// Link to the item, its `href` is resolved against the page URL
+    #[serde(default = "default_url")]
+    pub url: String,
+    /// Text like "In stock" or "Open box", matched by rules like a flair
+    pub stock: Option<String>,
+    /// Product type the items are filed under, like the `[Type]` tag in a
+    /// buildapcsales title
+    #[serde(default = "default_product_type")]
+    pub product_type: String,
+}
+
+fn default_url() -> String {
+    "a".to_owned()
+}
+
+fn default_product_type() -> String {
+    "Other".to_owned()
+}
+
+struct Compiled {
+    container: Selector,
+    title: Selector,
+    price: Selector,
+    url: Selector,
+    stock: Option<Selector>,
+}
+
+impl Compiled {
+    fn new(selectors: &Selectors) -> Result<Self, Error> {
+        let compile = |selector: &str| {
+            Selector::parse(selector).map_err(|e| Error::Other(format!("invalid selector {selector:?}: {e}")))
+        };
+
+        Ok(Self {
+            container: compile(&selectors.container)?,
+            title: compile(&selectors.title)?,
+            price: compile(&selectors.price)?,
+            url: compile(&selectors.url)?,
+            stock: selectors.stock.as_deref().map(compile).transpose()?,
+        })
+    }
+}
+
+/// A listing page scraped with CSS selectors.
+pub struct HtmlSource {
+    name: String,
+    url: Url,
+    product_type: String,
+    selectors: Compiled,
+    http: Arc<dyn HttpClient>,
+}
+
+/// A price in an item's price text, which may have a currency symbol or
+/// words around it.
+static PRICE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\d[\d,]*(?:\.\d{1,2})?").unwrap());
+
+impl HtmlSource {
+    pub fn new(name: &str, url: &str, selectors: &Selectors, http: Arc<dyn HttpClient>) -> Result<Self, Error> {
+        Ok(Self {
+            name: name.to_owned(),
+            url: Url::parse(url)?,
+            product_type: selectors.product_type.clone(),
+            selectors: Compiled::new(selectors)?,
+            http,
+        })
+    }
+
+    /// Every item on the page, in page order. Items without a title, link or
+    /// readable price are skipped.
+    fn parse_page(&self, body: &str) -> Vec<Post> {
+        let document = Html::parse_document(body);
+
+        document
+            .select(&self.selectors.container)
+            .filter_map(|container| {
+                let title = first_text(container, &self.selectors.title)?;
+                let url = container
+                    .select(&self.selectors.url)
+                    .find_map(|link| link.value().attr("href"))
+                    .and_then(|href| self.url.join(href).ok());
+                let price = first_text(container, &self.selectors.price)
+                    .and_then(|text| PRICE.find(&text).and_then(|m| parser::parse_price(m.as_str())));
+                let (Some(url), Some((dollars, cents))) = (url, price) else {
+                    log::warn!("Skipping item {title:?} without a link or price on {}", self.name);
+                    return None;
+                };
+                let stock = self.selectors.stock.as_ref().and_then(|stock| first_text(container, stock));
+
+                // Written in the buildapcsales format so the default title
+                // parser turns it into a `Title`. The price is part of the id
+                // so a price change shows up as a new post.
+                Some(
+                    Item {
+                        id: format!("{url}@{dollars}.{cents:02}"),
+                        title: format!("[{}] {} ${dollars}.{cents:02}", self.product_type, title.replace('$', "")),
+                        url: url.to_string(),
+                        created_utc: None,
+                        author: None,
+                        body: String::new(),
+                        thumbnail: None,
+                        flair: stock,
+                    }
+                    .into_post(&self.name, Kind::Html),
+                )
+            })
+            .collect()
+    }
+}
+
+impl Source for HtmlSource {
+    /// Pages have no order to resume from, so every item is returned and
+    /// ones already stored are dropped when the posts are inserted.
+    fn fetch_new<'a>(&'a mut self, _last_seen: Option<&'a Cursor>) -> BoxFuture<'a, Result<Vec<Post>, Error>> {
+        Box::pin(async move {
+            let body = feed::fetch(self.http.as_ref(), self.url.as_str()).await?;
+            Ok(self.parse_page(&body))
+        })
+    }
+}
+
+/// The whitespace-collapsed text of the first match, if it has any.
+fn first_text(element: ElementRef, selector: &Selector) -> Option<String> {
+    let text = element
+        .select(selector)
+        .next()?
+        .text()
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{http, parser::TitleParser, testing::{self, StandInResponse}};
+
+    #[tokio::test]
+    async fn test_scrape_listing_page() {
+        let base = testing::serve(|request| match request.path() {
+            "/deals" => StandInResponse::ok("text/html", include_str!("../tests/fixtures/html/deals.html")),
+            _ => StandInResponse::status(404),
+        })
+        .await;
+        let selectors: Selectors = toml::from_str(r#"
+            container = "li.product"
+            title = ".name"
+            price = ".price .now"
+            url = "a.details"
+            stock = ".stock"
+            product_type = "SSD"
+        "#).unwrap();
+        let http = http::ReqwestClient::shared(&http::Config::default()).unwrap();
+        let mut source = HtmlSource::new("shop", base.join("deals").unwrap().as_str(), &selectors, http).unwrap();
+
+        let posts = source.fetch_new(None).await.unwrap();
+
+        // The item without a price is skipped
+        assert_eq!(posts.len(), 2);
+        let post = &posts[0];
+        assert_eq!(post.title, "[SSD] Samsung 990 Pro 2TB NVMe SSD $1149.99");
+        assert_eq!(post.url, base.join("p/990-pro-2tb").unwrap().as_str());
+        assert_eq!(post.id, format!("shop:{}@1149.99", post.url));
+        assert_eq!(post.link_flair_text.as_deref(), Some("In stock"));
+        assert_eq!(post.subreddit, "shop");
+        assert_eq!(post.source_kind, Kind::Html);
+        assert_eq!(posts[1].url, "https://cdn.example.com/p/wd-sn850x");
+        assert_eq!(posts[1].link_flair_text.as_deref(), Some("Open box - like new"));
+
+        let titles = parser::BuildapcsalesParser.parse(&post.title, &post.id).unwrap();
+        assert_eq!(titles[0].product_type, "SSD");
+        assert_eq!(titles[0].description, "Samsung 990 Pro 2TB NVMe SSD");
+        assert_eq!((titles[0].price_dollars, titles[0].price_cents), (1149, 99));
+
+        let missing = HtmlSource::new("shop", base.join("gone").unwrap().as_str(), &selectors, source.http.clone());
+        assert!(missing.unwrap().fetch_new(None).await.is_err());
+    }
+
+    #[test]
+    fn test_invalid_selector() {
+        let selectors = Selectors {
+            container: "li..product".to_owned(),
+            title: ".name".to_owned(),
+            price: ".price".to_owned(),
+            url: default_url(),
+            stock: None,
+            product_type: default_product_type(),
+        };
+        let http = http::ReqwestClient::shared(&http::Config::default()).unwrap();
+        assert!(HtmlSource::new("shop", "https://shop.example.com/", &selectors, http).is_err());
+    }
+}
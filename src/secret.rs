@@ -0,0 +1,195 @@
+use std::{fmt, path::Path};
+
+use argon2::Argon2;
+use base64::{engine::general_purpose, Engine};
+use chacha20poly1305::{aead::{Aead, AeadCore, KeyInit, OsRng}, ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// A credential that shouldn't end up in logs. `Debug` and `Display` print a
+/// placeholder; use `expose` to get at the value.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Default)]
+#[serde(transparent)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    pub const fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl From<String> for Secret<String> {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for Secret<String> {
+    fn from(value: &str) -> Self {
+        Self(value.to_owned())
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret([redacted])")
+    }
+}
+
+impl<T> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+/// Replaces `path` with `contents` by writing a temporary file next to it and
+/// renaming it over, so a crash never leaves a half-written file. The file is
+/// only readable by its owner.
+pub async fn write_private_file(path: &str, contents: &[u8]) -> Result<(), Error> {
+    use tokio::io::AsyncWriteExt;
+
+    if let Some(parent) = Path::new(path).parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let tmp_path = format!("{path}.tmp");
+    // A leftover temporary file would keep its old permissions
+    let _ = tokio::fs::remove_file(&tmp_path).await;
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let mut file = options.open(&tmp_path).await?;
+    file.write_all(contents).await?;
+    file.sync_all().await?;
+    drop(file);
+
+    tokio::fs::rename(&tmp_path, path).await?;
+    Ok(())
+}
+
+/// Contents of a file encrypted with a passphrase: ChaCha20-Poly1305 with a
+/// key derived by Argon2id.
+#[derive(Serialize, Deserialize)]
+pub struct Encrypted {
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+const SALT_LEN: usize = 16;
+
+impl Encrypted {
+    pub fn open(&self, cipher: &Cipher) -> Result<Vec<u8>, Error> {
+        let nonce = decode(&self.nonce)?;
+        if nonce.len() != 12 {
+            return Err(Error::Other("invalid encrypted file: bad nonce".to_owned()));
+        }
+
+        cipher.cipher
+            .decrypt(Nonce::from_slice(&nonce), decode(&self.ciphertext)?.as_slice())
+            .map_err(|_| Error::Other("couldn't decrypt, wrong passphrase?".to_owned()))
+    }
+
+    pub fn salt(&self) -> Result<Vec<u8>, Error> {
+        decode(&self.salt)
+    }
+}
+
+/// A key derived from a passphrase. Deriving is deliberately slow, so one
+/// `Cipher` is kept around for every write of the same file.
+pub struct Cipher {
+    salt: Vec<u8>,
+    cipher: ChaCha20Poly1305,
+}
+
+impl Cipher {
+    pub fn new(passphrase: &Secret<String>) -> Result<Self, Error> {
+        let mut salt = [0_u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self::with_salt(passphrase, &salt)
+    }
+
+    pub fn with_salt(passphrase: &Secret<String>, salt: &[u8]) -> Result<Self, Error> {
+        let mut key = Key::default();
+        Argon2::default()
+            .hash_password_into(passphrase.expose().as_bytes(), salt, &mut key)
+            .map_err(|e| Error::Other(format!("key derivation failed: {e}")))?;
+
+        Ok(Self {
+            salt: salt.to_vec(),
+            cipher: ChaCha20Poly1305::new(&key),
+        })
+    }
+
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Encrypted, Error> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| Error::Other("encryption failed".to_owned()))?;
+
+        Ok(Encrypted {
+            salt: general_purpose::STANDARD.encode(&self.salt),
+            nonce: general_purpose::STANDARD.encode(nonce),
+            ciphertext: general_purpose::STANDARD.encode(ciphertext),
+        })
+    }
+}
+
+fn decode(field: &str) -> Result<Vec<u8>, Error> {
+    general_purpose::STANDARD
+        .decode(field)
+        .map_err(|e| Error::Other(format!("invalid encrypted file: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_redacted() {
+        let secret = Secret::from("hunter2");
+        assert_eq!(format!("{secret}"), "[redacted]");
+        assert!(!format!("{:?}", Some(&secret)).contains("hunter2"));
+        assert_eq!(secret.expose(), "hunter2");
+        assert_eq!(serde_json::to_string(&secret).unwrap(), "\"hunter2\"");
+    }
+
+    #[test]
+    fn test_encrypted_roundtrip() {
+        let cipher = Cipher::new(&Secret::from("correct horse")).unwrap();
+        let sealed = cipher.seal(b"token").unwrap();
+        let json = serde_json::to_string(&sealed).unwrap();
+        assert!(!json.contains("token"));
+
+        let sealed: Encrypted = serde_json::from_str(&json).unwrap();
+        let salt = sealed.salt().unwrap();
+        let reopened = Cipher::with_salt(&Secret::from("correct horse"), &salt).unwrap();
+        assert_eq!(sealed.open(&reopened).unwrap(), b"token");
+
+        let wrong = Cipher::with_salt(&Secret::from("wrong"), &salt).unwrap();
+        assert!(sealed.open(&wrong).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_private_file() {
+        let dir = std::env::temp_dir().join(format!("sales_crawler_secret_{}", std::process::id()));
+        let path = dir.join("token.json");
+        let path = path.to_str().unwrap();
+
+        write_private_file(path, b"first").await.unwrap();
+        write_private_file(path, b"second").await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), b"second");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
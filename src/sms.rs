@@ -1,32 +1,39 @@
-use std::fs;
+use std::{fs, sync::Arc};
 
 use serde::Deserialize;
 use url::Url;
 
-use crate::{auth::make_basic_auth_header, error::Error};
+use crate::{auth::make_basic_auth_header, error::Error, http::{HttpClient, Request}, secret::Secret};
 
 #[derive(Deserialize, PartialEq)]
 pub struct Config {
     api_url: String,
     api_key: String,
-    api_key_secret: String,
+    api_key_secret: Secret<String>,
     account_sid: String,
     phone_number_from: String,
     phone_number_to: String,
 }
 
+// Nothing sends texts yet
+#[allow(dead_code)]
 pub struct Client {
     config: Config,
+    http: Arc<dyn HttpClient>,
 }
 
+#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 pub struct SendMessageResponseBody {
     pub uri: String,
 }
+
+#[allow(dead_code)]
 impl Client {
-    pub const fn new(config: Config) -> Self {
+    pub fn new(config: Config, http: Arc<dyn HttpClient>) -> Self {
         Self {
-            config
+            config,
+            http,
         }
     }
 
@@ -38,26 +45,21 @@ impl Client {
     // --data-urlencode "To=+12316851234" \
     // -u $TWILIO_ACCOUNT_SID:$TWILIO_AUTH_TOKEN
 
-    pub fn send_message(&self, body: &str) -> Result<SendMessageResponseBody, Error> {
+    pub async fn send_message(&self, body: &str) -> Result<SendMessageResponseBody, Error> {
         let uri = format!("{}/Messages.json", self.config.account_sid);
         let api_url = Url::parse(self.config.api_url.as_str())?.join(&uri)?;
 
-        let auth = make_basic_auth_header(&self.config.api_key, &self.config.api_key_secret);
-
-        let body = format!("Body={}&From={}&To={}",
-            &urlencoding::encode(body).to_string(),
-            &urlencoding::encode(&self.config.phone_number_from).to_string(),
-            &urlencoding::encode(&self.config.phone_number_to).to_string());
+        let auth = make_basic_auth_header(&self.config.api_key, self.config.api_key_secret.expose());
 
-        let resp = ureq::post(api_url.as_str())
-            .set("Authorization", &auth)
-            .set("Content-Type", "application/x-www-form-urlencoded")
-            .send_string(&body).map_err( Box::new)?;
+        let request = Request::post(api_url)
+            .header("Authorization", &auth)
+            .form(&[
+                ("Body", body),
+                ("From", &self.config.phone_number_from),
+                ("To", &self.config.phone_number_to),
+            ]);
 
-        let body = resp.into_string()?;
-        let parsed: SendMessageResponseBody = serde_json::from_str(&body)?;
-        
-        Ok(parsed)
+        self.http.send(request).await?.error_for_status()?.json()
     }
 }
 
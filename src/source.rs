@@ -0,0 +1,228 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::{error::Error, feed, http::{BoxFuture, HttpClient}, models::Post, parser, reddit, scrape};
+
+pub const DEFAULT_CURRENCY: &str = "USD";
+
+/// Where posts come from.
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum Kind {
+    /// A subreddit's `/new` listing, through the OAuth API
+    #[default]
+    Reddit,
+    /// Newest results of a Reddit search
+    RedditSearch,
+    /// A multireddit's `/new` listing
+    Multireddit,
+    /// An RSS or Atom feed, including Reddit's own `.rss` listings, whose
+    /// posts are stored as `Reddit` posts
+    Rss,
+    /// A JSON document with a list of items, JSON Feed by default
+    JsonFeed,
+    /// A web page, scraped with CSS selectors
+    Html,
+    /// Pushed to the webhook listener rather than polled
+    #[serde(skip_deserializing)]
+    Webhook,
+}
+
+impl Kind {
+    /// Whether posts come from the Reddit API, sharing its client and rate
+    /// limit.
+    pub const fn is_reddit_api(self) -> bool {
+        matches!(self, Self::Reddit | Self::RedditSearch | Self::Multireddit)
+    }
+}
+
+/// Where polling a source resumes from.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Cursor {
+    /// The newest post seen, as the source identifies it
+    pub id: String,
+    /// When that post was created, for sources that can resume from a time
+    /// once the post itself is gone
+    pub created_utc: Option<f64>,
+}
+
+/// Produces new posts from somewhere, normalized into `Post`s.
+pub trait Source: Send + Sync {
+    /// Posts newer than the one `last_seen` was the cursor of, oldest first.
+    fn fetch_new<'a>(&'a mut self, last_seen: Option<&'a Cursor>) -> BoxFuture<'a, Result<Vec<Post>, Error>>;
+
+    /// Cursor to resume after `post` from.
+    fn cursor(&self, post: &Post) -> Cursor {
+        Cursor {
+            id: post.id.clone(),
+            created_utc: None,
+        }
+    }
+}
+
+/// A source to poll, with settings that override the global defaults.
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct Config {
+    /// The subreddit for Reddit sources. Posts from other sources are filed
+    /// under this name in place of a subreddit, except for searches and
+    /// multireddits, whose posts keep their own subreddits.
+    #[serde(alias = "subreddit")]
+    pub name: String,
+    #[serde(default)]
+    pub kind: Kind,
+    /// Feed or page URL, for everything but Reddit sources
+    pub url: Option<String>,
+    /// Search query, for Reddit search sources
+    pub query: Option<String>,
+    /// `user/name` of a multireddit, for multireddit sources
+    pub multireddit: Option<String>,
+    /// Where to find each field in a JSON feed's items
+    #[serde(default)]
+    pub json_fields: feed::JsonFields,
+    /// Where to find each item on a scraped page, required for HTML sources
+    pub selectors: Option<scrape::Selectors>,
+    /// Seconds between polls, defaults to `reddit.wait_time_secs`
+    pub wait_time_secs: Option<u64>,
+    /// Title parser for this source, defaults to the buildapcsales format
+    pub title_parser: Option<parser::Kind>,
+    /// Pattern for the `regex` title parser
+    pub title_pattern: Option<String>,
+    /// Currency prices from this source are listed in, defaults to USD
+    pub currency: Option<String>,
+}
+
+impl Config {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            kind: Kind::Reddit,
+            url: None,
+            query: None,
+            multireddit: None,
+            json_fields: feed::JsonFields::default(),
+            selectors: None,
+            wait_time_secs: None,
+            title_parser: None,
+            title_pattern: None,
+            currency: None,
+        }
+    }
+
+    pub fn currency(&self) -> &str {
+        self.currency.as_deref().unwrap_or(DEFAULT_CURRENCY)
+    }
+
+    /// Rejects settings that would never apply. Posts from searches and
+    /// multireddits are parsed by the settings of the subreddit they were
+    /// posted in, so those sources can't have their own.
+    pub fn validate(&self) -> Result<(), Error> {
+        let keeps_subreddits = matches!(self.kind, Kind::RedditSearch | Kind::Multireddit);
+        if keeps_subreddits && (self.title_parser.is_some() || self.title_pattern.is_some() || self.currency.is_some()) {
+            return Err(Error::Other(format!(
+                "source {} can't set title_parser, title_pattern or currency, its posts use the settings of their subreddits",
+                self.name
+            )));
+        }
+        Ok(())
+    }
+
+    /// How the source shows up in logs and alerts.
+    pub fn label(&self) -> String {
+        match self.kind {
+            Kind::Reddit => format!("r/{}", self.name),
+            Kind::RedditSearch => format!("search {}", self.name),
+            Kind::Multireddit => format!("m/{}", self.name),
+            _ => self.name.clone(),
+        }
+    }
+
+    /// Creates the source. Reddit sources share `reddit_client`, so they all
+    /// count against one rate limit.
+    pub fn build(&self, reddit_client: &Arc<Mutex<reddit::Client>>, http: &Arc<dyn HttpClient>) -> Result<Box<dyn Source>, Error> {
+        let url = || self.url.clone().ok_or_else(|| Error::Other(format!("source {} needs a url", self.name)));
+        let listing = |listing| Box::new(reddit::ListingSource::new(listing, reddit_client.clone()));
+        Ok(match self.kind {
+            Kind::Reddit => listing(reddit::Listing::Subreddit(self.name.clone())),
+            Kind::RedditSearch => {
+                let query = self.query.clone().ok_or_else(|| Error::Other(format!("source {} needs a query", self.name)))?;
+                listing(reddit::Listing::Search(query))
+            }
+            Kind::Multireddit => {
+                let multireddit = self.multireddit.as_deref().ok_or_else(|| Error::Other(format!("source {} needs a multireddit", self.name)))?;
+                listing(reddit::Listing::multireddit(multireddit)?)
+            }
+            Kind::Rss => Box::new(feed::RssSource::new(&self.name, &url()?, http.clone())),
+            Kind::JsonFeed => Box::new(feed::JsonFeedSource::new(&self.name, &url()?, self.json_fields.clone(), http.clone())),
+            Kind::Html => {
+                let selectors = self.selectors.as_ref().ok_or_else(|| Error::Other(format!("source {} needs selectors", self.name)))?;
+                Box::new(scrape::HtmlSource::new(&self.name, &url()?, selectors, http.clone())?)
+            }
+            Kind::Webhook => return Err(Error::Other(format!("source {} can't be polled", self.name))),
+        })
+    }
+
+    pub fn parser_config(&self) -> Option<parser::Config> {
+        self.title_parser.map(|kind| parser::Config {
+            source: self.name.clone(),
+            kind,
+            pattern: self.title_pattern.clone(),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Sources(pub Vec<Config>);
+
+impl Sources {
+    pub fn get(&self, name: &str) -> Option<&Config> {
+        self.0.iter().find(|source| source.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn currency(&self, name: &str) -> &str {
+        self.get(name).map_or(DEFAULT_CURRENCY, Config::currency)
+    }
+
+    pub fn has_reddit(&self) -> bool {
+        self.0.iter().any(|source| source.kind.is_reddit_api())
+    }
+
+    pub fn parser_configs(&self) -> impl Iterator<Item = parser::Config> + '_ {
+        self.0.iter().filter_map(Config::parser_config)
+    }
+}
+
+pub fn currency_symbol(currency: &str) -> String {
+    match currency.to_uppercase().as_str() {
+        "USD" => "$".to_owned(),
+        "CAD" => "CA$".to_owned(),
+        "AUD" => "A$".to_owned(),
+        "EUR" => "€".to_owned(),
+        "GBP" => "£".to_owned(),
+        other => format!("{other} "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sources_lookup() {
+        let sources = Sources(vec![
+            Config::new("buildapcsales"),
+            Config {
+                currency: Some("CAD".to_owned()),
+                title_parser: Some(parser::Kind::Generic),
+                ..Config::new("bapcsalescanada")
+            },
+        ]);
+
+        assert_eq!(sources.currency("BapcSalesCanada"), "CAD");
+        assert_eq!(sources.currency("buildapcsales"), "USD");
+        assert_eq!(sources.currency("unknown"), "USD");
+        assert_eq!(sources.parser_configs().count(), 1);
+    }
+}
@@ -0,0 +1,353 @@
+//! Helpers for tests that talk HTTP: a generic stand-in server, plus fake
+//! Reddit and Discord APIs built on it.
+
+use std::{collections::{HashMap, VecDeque}, path::{Path, PathBuf}, sync::{atomic::{AtomicU32, Ordering}, Arc, Mutex}};
+
+use serde_json::{json, Value};
+use tokio::{io::AsyncWriteExt, net::{TcpListener, TcpStream}};
+use url::Url;
+
+/// A request received by a stand-in server.
+#[derive(Debug, Clone)]
+pub struct StandInRequest {
+    pub method: String,
+    /// Path and query, e.g. `/r/buildapcsales/new?limit=100`
+    pub target: String,
+    /// Names are lowercase
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+impl StandInRequest {
+    pub fn path(&self) -> &str {
+        self.target.split('?').next().unwrap_or_default()
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(String::as_str)
+    }
+
+    pub fn query(&self, name: &str) -> Option<String> {
+        let (_, query) = self.target.split_once('?')?;
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    }
+}
+
+/// A canned response from a stand-in server.
+#[derive(Debug, Clone)]
+pub struct StandInResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl StandInResponse {
+    pub fn ok(content_type: &str, body: impl Into<String>) -> Self {
+        Self::status(200).header("Content-Type", content_type).body(body)
+    }
+
+    pub fn json(value: &Value) -> Self {
+        Self::ok("application/json", value.to_string())
+    }
+
+    pub const fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = body.into();
+        self
+    }
+}
+
+/// Serves HTTP on a random local port, answering every request with
+/// `handler`, and returns the base URL. The server stops with the runtime.
+pub async fn serve<F>(handler: F) -> Url
+where
+    F: Fn(&StandInRequest) -> StandInResponse + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+    let handler = Arc::new(handler);
+
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                return;
+            };
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                let _ = handle(stream, handler.as_ref()).await;
+            });
+        }
+    });
+
+    url
+}
+
+async fn handle<F>(mut stream: TcpStream, handler: &F) -> std::io::Result<()>
+where
+    F: Fn(&StandInRequest) -> StandInResponse,
+{
+    let Ok(request) = crate::webhook::read_request(&mut stream, usize::MAX).await else {
+        return Ok(());
+    };
+    let request = StandInRequest {
+        method: request.method,
+        target: request.path,
+        headers: request.headers.into_iter().collect(),
+        body: String::from_utf8_lossy(&request.body).into_owned(),
+    };
+
+    let response = handler(&request);
+    let mut out = format!("HTTP/1.1 {} Stand-in\r\nContent-Length: {}\r\nConnection: close\r\n", response.status, response.body.len());
+    for (name, value) in &response.headers {
+        out.push_str(&format!("{name}: {value}\r\n"));
+    }
+    out.push_str("\r\n");
+    out.push_str(&response.body);
+    stream.write_all(out.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// A directory under the system temp dir, removed when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        static COUNT: AtomicU32 = AtomicU32::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "sales_crawler-{name}-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// `sqlite://` URL for a database in the directory.
+    pub fn db_url(&self) -> String {
+        format!("sqlite://{}", self.0.join("sqlite.db").display())
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Requests allowed per rate limit window by the fake Reddit API.
+const REDDIT_RATELIMIT: u32 = 600;
+
+#[derive(Default)]
+struct RedditState {
+    /// Posts by listing path, newest first
+    listings: HashMap<String, Vec<Value>>,
+    /// Responses to send instead of the next API responses, or `None` to
+    /// answer one normally
+    faults: VecDeque<Option<StandInResponse>>,
+    /// Number of tokens issued, the current one is `token-{tokens}`
+    tokens: u32,
+    revoked: bool,
+    /// Report the rate limit as used up
+    exhausted: bool,
+    /// Path and query of each API request, in order
+    requests: Vec<String>,
+}
+
+/// Reddit's OAuth token endpoint and listing endpoints, serving listings from
+/// fixtures with rate limit headers. Failures can be queued up to test
+/// retries.
+pub struct FakeReddit {
+    pub url: Url,
+    state: Arc<Mutex<RedditState>>,
+}
+
+impl FakeReddit {
+    pub async fn start() -> Self {
+        let state = Arc::new(Mutex::new(RedditState::default()));
+        let handler_state = state.clone();
+        let url = serve(move |request| handler_state.lock().unwrap().respond(request)).await;
+        Self { url, state }
+    }
+
+    /// Serves the posts in a listing fixture, newest first, from `path`, e.g.
+    /// `/r/buildapcsales/new`.
+    pub fn listing(&self, path: &str, fixture: &str) {
+        let listing: Value = serde_json::from_str(fixture).unwrap();
+        let posts = listing["data"]["children"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|child| child["data"].clone())
+            .collect();
+        self.state.lock().unwrap().listings.insert(path.to_owned(), posts);
+    }
+
+    /// Answers the next API request with `status`. 429s ask for a retry
+    /// straight away.
+    pub fn fail_next(&self, status: u16) {
+        let mut response = StandInResponse::status(status).body(format!("{{\"error\": {status}}}"));
+        if status == 429 {
+            response = response.header("Retry-After", "0");
+        }
+        self.state.lock().unwrap().faults.push_back(Some(response));
+    }
+
+    /// Answers the next `requests` API requests normally, then the one after
+    /// them with `status`.
+    pub fn fail_after(&self, requests: usize, status: u16) {
+        self.state.lock().unwrap().faults.extend((0..requests).map(|_| None));
+        self.fail_next(status);
+    }
+
+    /// Rejects the current access token with 401s until a new one is issued.
+    pub fn revoke_token(&self) {
+        self.state.lock().unwrap().revoked = true;
+    }
+
+    /// Reports no requests left in the rate limit window from now on.
+    pub fn exhaust_ratelimit(&self) {
+        self.state.lock().unwrap().exhausted = true;
+    }
+
+    pub fn tokens_issued(&self) -> u32 {
+        self.state.lock().unwrap().tokens
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// The body of a `[reddit]` config table pointing at this server.
+    pub fn config_toml(&self, token_file: &Path) -> String {
+        format!(
+            r#"
+            auth_host = "{url}api/v1/"
+            api_host = "{url}"
+            token_file = "{token_file}"
+            grant = "client_credentials"
+            client_id = "id"
+            client_secret = "secret"
+            user_agent = "test"
+            wait_time_secs = 1
+            "#,
+            url = self.url,
+            token_file = token_file.display(),
+        )
+    }
+}
+
+impl RedditState {
+    fn respond(&mut self, request: &StandInRequest) -> StandInResponse {
+        if request.path() == "/api/v1/access_token" {
+            if request.method != "POST" || !request.header("Authorization").is_some_and(|auth| auth.starts_with("Basic ")) {
+                return StandInResponse::status(401);
+            }
+            self.tokens += 1;
+            self.revoked = false;
+            return StandInResponse::json(&json!({
+                "access_token": format!("token-{}", self.tokens),
+                "token_type": "bearer",
+                "expires_in": 3600,
+                "scope": "read",
+            }));
+        }
+
+        self.requests.push(request.target.clone());
+        let expected = format!("bearer token-{}", self.tokens);
+        if self.revoked || request.header("Authorization") != Some(expected.as_str()) {
+            return StandInResponse::status(401);
+        }
+        if let Some(Some(fault)) = self.faults.pop_front() {
+            return fault;
+        }
+        let Some(posts) = self.listings.get(request.path()) else {
+            return StandInResponse::status(404);
+        };
+
+        let limit: usize = request.query("limit").and_then(|limit| limit.parse().ok()).unwrap_or(25);
+        let start = match request.query("after") {
+            Some(after) => posts.iter().position(|post| format!("t3_{}", post["id"].as_str().unwrap()) == after).map_or(posts.len(), |i| i + 1),
+            None => 0,
+        };
+        let page: Vec<&Value> = posts.iter().skip(start).take(limit).collect();
+        let after = (start + page.len() < posts.len()).then(|| format!("t3_{}", page.last().unwrap()["id"].as_str().unwrap()));
+
+        let used = if self.exhausted { REDDIT_RATELIMIT as usize } else { self.requests.len() };
+        StandInResponse::json(&json!({
+            "kind": "Listing",
+            "data": {
+                "after": after,
+                "before": null,
+                "children": page.iter().map(|post| json!({ "kind": "t3", "data": post })).collect::<Vec<_>>(),
+            }
+        }))
+        .header("X-Ratelimit-Used", &used.to_string())
+        .header("X-Ratelimit-Remaining", &(REDDIT_RATELIMIT as usize).saturating_sub(used).to_string())
+        .header("X-Ratelimit-Reset", "300")
+    }
+}
+
+/// Discord's create message endpoint, keeping every message sent to it.
+pub struct FakeDiscord {
+    pub url: Url,
+    messages: Arc<Mutex<Vec<Value>>>,
+}
+
+impl FakeDiscord {
+    pub async fn start() -> Self {
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let handler_messages = messages.clone();
+        let url = serve(move |request| {
+            let Some(channel_id) = request.path().strip_prefix("/channels/").and_then(|path| path.strip_suffix("/messages")) else {
+                return StandInResponse::status(404);
+            };
+            if request.method != "POST" || request.header("Authorization") != Some("Bot token") {
+                return StandInResponse::status(401);
+            }
+
+            let mut messages = handler_messages.lock().unwrap();
+            messages.push(serde_json::from_str(&request.body).unwrap());
+            StandInResponse::json(&json!({ "id": format!("message-{}", messages.len()), "channel_id": channel_id }))
+                .header("X-RateLimit-Remaining", "5")
+                .header("X-RateLimit-Reset-After", "1")
+        })
+        .await;
+        Self { url, messages }
+    }
+
+    pub fn messages(&self) -> Vec<Value> {
+        self.messages.lock().unwrap().clone()
+    }
+
+    /// The body of a `[discord]` config table pointing at this server.
+    pub fn config_toml(&self) -> String {
+        format!(
+            r#"
+            token = "token"
+            user_agent = "test"
+            api_url = "{}"
+            channel_id = "123"
+            sending_interval_secs = 1
+            "#,
+            self.url
+        )
+    }
+}
@@ -0,0 +1,273 @@
+use std::{fmt, str::FromStr, sync::LazyLock};
+
+use regex::Regex;
+use serde::{Deserialize, Deserializer, de::{self, Visitor}};
+use thiserror::Error;
+
+use crate::{attributes::Comparison, models::Post};
+
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct Config {
+    /// How often recent posts are re-fetched to snapshot their score
+    #[serde(default = "default_snapshot_interval_secs")]
+    pub snapshot_interval_secs: u64,
+    /// Stop snapshotting posts older than this
+    #[serde(default = "default_track_hours")]
+    pub track_hours: u64,
+}
+
+const fn default_snapshot_interval_secs() -> u64 {
+    600
+}
+
+const fn default_track_hours() -> u64 {
+    6
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            snapshot_interval_secs: default_snapshot_interval_secs(),
+            track_hours: default_track_hours(),
+        }
+    }
+}
+
+/// A post as returned by `/by_id`, with the counts that change over time.
+#[derive(Deserialize, Debug)]
+pub struct PostStats {
+    #[serde(flatten)]
+    pub post: Post,
+    pub score: i64,
+    pub upvote_ratio: f64,
+}
+
+/// Score and comment counts of a post at one point in time.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Snapshot {
+    pub taken_utc: f64,
+    pub score: i64,
+    pub upvote_ratio: f64,
+    pub num_comments: i64,
+}
+
+impl Snapshot {
+    pub const fn of(stats: &PostStats, taken_utc: f64) -> Self {
+        Self {
+            taken_utc,
+            score: stats.score,
+            upvote_ratio: stats.upvote_ratio,
+            num_comments: stats.post.num_comments,
+        }
+    }
+}
+
+/// How a post is doing, with rates measured since the previous snapshot, or
+/// since the post was created for the first one.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Popularity {
+    pub score: i64,
+    pub num_comments: i64,
+    pub upvote_ratio: f64,
+    pub score_per_hour: f64,
+    pub comments_per_hour: f64,
+}
+
+impl Popularity {
+    #[allow(clippy::cast_precision_loss)]
+    pub fn between(previous: Option<&Snapshot>, current: &Snapshot, created_utc: f64) -> Self {
+        // New posts start with a score of 1 from their author's upvote
+        let (since, score, comments) = previous.map_or((created_utc, 1, 0), |previous| {
+            (previous.taken_utc, previous.score, previous.num_comments)
+        });
+        // Avoid huge rates from snapshots taken moments apart
+        let hours = ((current.taken_utc - since) / 3600.0).max(1.0 / 60.0);
+
+        Self {
+            score: current.score,
+            num_comments: current.num_comments,
+            upvote_ratio: current.upvote_ratio,
+            score_per_hour: (current.score - score) as f64 / hours,
+            comments_per_hour: (current.num_comments - comments) as f64 / hours,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Metric {
+    ScoreVelocity,
+    CommentVelocity,
+    Score,
+    Comments,
+    UpvoteRatio,
+}
+
+impl Metric {
+    #[allow(clippy::cast_precision_loss)]
+    const fn get(self, popularity: &Popularity) -> f64 {
+        match self {
+            Self::ScoreVelocity => popularity.score_per_hour,
+            Self::CommentVelocity => popularity.comments_per_hour,
+            Self::Score => popularity.score as f64,
+            Self::Comments => popularity.num_comments as f64,
+            Self::UpvoteRatio => popularity.upvote_ratio,
+        }
+    }
+
+    const fn is_rate(self) -> bool {
+        matches!(self, Self::ScoreVelocity | Self::CommentVelocity)
+    }
+}
+
+impl FromStr for Metric {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "score_velocity" => Ok(Self::ScoreVelocity),
+            "comment_velocity" | "comments_velocity" => Ok(Self::CommentVelocity),
+            "score" => Ok(Self::Score),
+            "comments" | "num_comments" => Ok(Self::Comments),
+            "upvote_ratio" => Ok(Self::UpvoteRatio),
+            _ => Err(Error::UnknownMetric(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Error)]
+pub enum Error {
+    #[error("unknown metric {0}")]
+    UnknownMetric(String),
+    #[error("unknown comparison {0}")]
+    UnknownComparison(String),
+    #[error("only velocities can have a rate, got: {0}")]
+    UnexpectedRate(String),
+    #[error("expected `<metric> <comparison> <number>[/h]`, got: {0}")]
+    Malformed(String),
+}
+
+/// A `<metric> <comparison> <number>` filter on a post's popularity, e.g.
+/// `score_velocity > 20/h`. Velocities are per hour unless given as `/m`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct VelocityFilter {
+    pub source: String,
+    pub metric: Metric,
+    pub comparison: Comparison,
+    pub value: f64,
+}
+
+impl VelocityFilter {
+    pub fn is_match(&self, popularity: &Popularity) -> bool {
+        self.comparison.compare(self.metric.get(popularity), self.value)
+    }
+}
+
+static FILTER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\s*([a-z_]+)\s*(<=|>=|==|!=|<|>|=)\s*(\d+(?:\.\d+)?)\s*(?:/\s*(h|hr|hour|m|min|minute))?\s*$").unwrap()
+});
+
+impl FromStr for VelocityFilter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(c) = FILTER.captures(s) else {
+            return Err(Error::Malformed(s.to_owned()));
+        };
+
+        let metric: Metric = c[1].parse()?;
+        let comparison = c[2]
+            .parse()
+            .map_err(|_| Error::UnknownComparison(c[2].to_owned()))?;
+        let mut value: f64 = c[3].parse().map_err(|_| Error::Malformed(s.to_owned()))?;
+        if let Some(unit) = c.get(4) {
+            if !metric.is_rate() {
+                return Err(Error::UnexpectedRate(s.to_owned()));
+            }
+            if unit.as_str().starts_with('m') {
+                value *= 60.0;
+            }
+        }
+
+        Ok(Self {
+            source: s.to_owned(),
+            metric,
+            comparison,
+            value,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for VelocityFilter {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct VelocityFilterVisitor;
+        impl<'de> Visitor<'de> for VelocityFilterVisitor {
+            type Value = VelocityFilter;
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+                where
+                    E: de::Error, {
+                v.parse()
+                    .map_err(|e| de::Error::custom(format!("failed to parse velocity filter: {e}")))
+            }
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("expected a string")
+            }
+        }
+
+        deserializer.deserialize_str(VelocityFilterVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    fn snapshot(taken_utc: f64, score: i64, num_comments: i64) -> Snapshot {
+        Snapshot { taken_utc, score, upvote_ratio: 0.9, num_comments }
+    }
+
+    #[test]
+    fn test_popularity_between_snapshots() {
+        let first = snapshot(1800.0, 11, 4);
+        let popularity = Popularity::between(None, &first, 0.0);
+        assert_close(popularity.score_per_hour, 20.0);
+        assert_close(popularity.comments_per_hour, 8.0);
+
+        let second = snapshot(3600.0, 41, 5);
+        let popularity = Popularity::between(Some(&first), &second, 0.0);
+        assert_close(popularity.score_per_hour, 60.0);
+        assert_close(popularity.comments_per_hour, 2.0);
+    }
+
+    #[test]
+    fn test_parse_velocity_filter() {
+        let filter: VelocityFilter = "score_velocity > 20/h".parse().unwrap();
+        assert_eq!(filter.metric, Metric::ScoreVelocity);
+        assert_eq!(filter.comparison, Comparison::Gt);
+        assert_close(filter.value, 20.0);
+
+        let filter: VelocityFilter = "comment_velocity >= 1/m".parse().unwrap();
+        assert_close(filter.value, 60.0);
+
+        assert_eq!("score > 5/h".parse::<VelocityFilter>(), Err(Error::UnexpectedRate("score > 5/h".to_owned())));
+        assert!("karma > 5".parse::<VelocityFilter>().is_err());
+        assert!("score_velocity ~ 5".parse::<VelocityFilter>().is_err());
+    }
+
+    #[test]
+    fn test_velocity_filter_match() {
+        let popularity = Popularity::between(None, &snapshot(1800.0, 11, 0), 0.0);
+
+        assert!("score_velocity > 19/h".parse::<VelocityFilter>().unwrap().is_match(&popularity));
+        assert!(!"score_velocity > 20/h".parse::<VelocityFilter>().unwrap().is_match(&popularity));
+        assert!("upvote_ratio >= 0.9".parse::<VelocityFilter>().unwrap().is_match(&popularity));
+    }
+}
@@ -0,0 +1,320 @@
+use std::{net::SocketAddr, time::Duration};
+
+use serde::Deserialize;
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::{TcpListener, TcpStream}, sync::mpsc};
+use url::Url;
+
+use crate::{error::Error, feed::Item, models::Post, parser, secret::Secret, source::Kind};
+
+/// The only path submissions are accepted on.
+const PATH: &str = "/posts";
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A local HTTP listener other tools can submit deals to.
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct Config {
+    /// Address to listen on. Keep it on localhost unless something in front
+    /// of it handles TLS.
+    #[serde(default = "default_listen")]
+    pub listen: String,
+    /// Requests must send this in `header`
+    pub secret: Secret<String>,
+    #[serde(default = "default_header")]
+    pub header: String,
+    /// Larger request bodies are rejected
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+}
+
+fn default_listen() -> String {
+    "127.0.0.1:8787".to_owned()
+}
+
+fn default_header() -> String {
+    "X-Webhook-Secret".to_owned()
+}
+
+const fn default_max_body_bytes() -> usize {
+    64 * 1024
+}
+
+/// A deal pushed to the listener.
+#[derive(Deserialize, Debug)]
+struct Submission {
+    /// In the format of the source's title parser, without the price if
+    /// `price` is given
+    title: String,
+    url: String,
+    /// Appended to the title, e.g. `449` or `"1,299.99"`
+    price: Option<Price>,
+    /// Filed under this name wherever a subreddit would be used
+    #[serde(default = "default_source")]
+    source: String,
+    /// Unique within the source, defaults to the url. Resubmitting an id
+    /// that's already stored does nothing.
+    id: Option<String>,
+}
+
+fn default_source() -> String {
+    "webhook".to_owned()
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Price {
+    Number(f64),
+    Text(String),
+}
+
+impl Submission {
+    fn into_post(self) -> Result<Post, String> {
+        if self.title.trim().is_empty() || self.source.trim().is_empty() {
+            return Err("title and source can't be empty".to_owned());
+        }
+        Url::parse(&self.url).map_err(|e| format!("invalid url: {e}"))?;
+
+        let title = match self.price {
+            Some(price) => {
+                let text = match price {
+                    Price::Number(n) => format!("{n:.2}"),
+                    Price::Text(s) => s,
+                };
+                let (dollars, cents) = parser::parse_price(&text).ok_or_else(|| format!("invalid price: {text}"))?;
+                format!("{} ${dollars}.{cents:02}", self.title.trim().replace('$', ""))
+            }
+            None => self.title.trim().to_owned(),
+        };
+
+        Ok(Item {
+            id: self.id.unwrap_or_else(|| self.url.clone()),
+            title,
+            url: self.url,
+            created_utc: None,
+            author: None,
+            body: String::new(),
+            thumbnail: None,
+            flair: None,
+        }
+        .into_post(&self.source, Kind::Webhook))
+    }
+}
+
+pub struct Listener {
+    config: Config,
+    listener: TcpListener,
+}
+
+impl Listener {
+    /// Binds straight away, so a taken port is reported on startup.
+    pub async fn bind(config: Config) -> Result<Self, Error> {
+        let listener = Self {
+            listener: TcpListener::bind(&config.listen).await?,
+            config,
+        };
+        log::info!("Accepting posts on http://{}{PATH}", listener.local_addr()?);
+        Ok(listener)
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Sends accepted posts to `tx`, where they're stored and matched like
+    /// polled posts. Only returns if the listener fails.
+    pub async fn run(self, tx: mpsc::Sender<Post>) -> Result<(), Error> {
+        loop {
+            let (stream, addr) = self.listener.accept().await?;
+            let config = self.config.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle(stream, &config, &tx).await {
+                    log::warn!("Webhook request from {addr} failed: {e}");
+                }
+            });
+        }
+    }
+}
+
+async fn handle(mut stream: TcpStream, config: &Config, tx: &mpsc::Sender<Post>) -> Result<(), Error> {
+    let (status, body) = match tokio::time::timeout(READ_TIMEOUT, read_request(&mut stream, config.max_body_bytes)).await {
+        Ok(Ok(request)) => respond(&request, config, tx).await,
+        Ok(Err(rejection)) => rejection,
+        Err(_) => (408, "timed out".to_owned()),
+    };
+
+    let reason = match status {
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        _ => "Service Unavailable",
+    };
+    let body = serde_json::json!({ "status": reason, "message": body }).to_string();
+    let response = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(stream.shutdown().await?)
+}
+
+/// An HTTP request as read off the wire. The test servers read requests with
+/// this too.
+pub(crate) struct Request {
+    pub(crate) method: String,
+    /// Path and query
+    pub(crate) path: String,
+    /// Names are lowercase
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        self.headers.iter().find(|(n, _)| *n == name).map(|(_, value)| value.as_str())
+    }
+}
+
+/// Reads one HTTP/1.1 request, or the status and message to reject it with.
+pub(crate) async fn read_request(stream: &mut TcpStream, max_body_bytes: usize) -> Result<Request, (u16, String)> {
+    const MAX_HEAD_BYTES: usize = 16 * 1024;
+    let io_error = |e: std::io::Error| (400, e.to_string());
+
+    let mut buf = Vec::new();
+    let mut chunk = [0; 4096];
+    let head_end = loop {
+        let n = stream.read(&mut chunk).await.map_err(io_error)?;
+        if n == 0 {
+            return Err((400, "incomplete request".to_owned()));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i + 4;
+        }
+        if buf.len() > MAX_HEAD_BYTES {
+            return Err((413, "headers too large".to_owned()));
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..head_end]).into_owned();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_owned();
+    let path = request_line.next().unwrap_or_default().to_owned();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_owned()))
+        .collect();
+
+    let content_length = match headers.iter().find(|(name, _)| name == "content-length") {
+        Some((_, len)) => len.parse::<usize>().map_err(|_| (400, "invalid content-length".to_owned()))?,
+        None => 0,
+    };
+    if content_length > max_body_bytes {
+        return Err((413, format!("body is over {max_body_bytes} bytes")));
+    }
+    while buf.len() < head_end + content_length {
+        let n = stream.read(&mut chunk).await.map_err(io_error)?;
+        if n == 0 {
+            return Err((400, "incomplete body".to_owned()));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    buf.truncate(head_end + content_length);
+
+    Ok(Request {
+        method,
+        path,
+        headers,
+        body: buf.split_off(head_end),
+    })
+}
+
+async fn respond(request: &Request, config: &Config, tx: &mpsc::Sender<Post>) -> (u16, String) {
+    if request.path.split('?').next() != Some(PATH) {
+        return (404, format!("submit posts to {PATH}"));
+    }
+    if request.method != "POST" {
+        return (405, "use POST".to_owned());
+    }
+    let secret = request.header(&config.header).unwrap_or_default();
+    if !constant_time_eq(secret.as_bytes(), config.secret.expose().as_bytes()) {
+        return (401, format!("missing or wrong {}", config.header));
+    }
+
+    let post = match serde_json::from_slice::<Submission>(&request.body) {
+        Ok(submission) => match submission.into_post() {
+            Ok(post) => post,
+            Err(message) => return (400, message),
+        },
+        Err(e) => return (400, e.to_string()),
+    };
+
+    let id = post.id.clone();
+    match tx.send(post).await {
+        Ok(()) => (202, id),
+        Err(_) => (503, "not accepting posts".to_owned()),
+    }
+}
+
+/// Compares without returning early, so timing doesn't give the secret away.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{self, Request};
+
+    #[tokio::test]
+    async fn test_submit_posts() {
+        let config: Config = toml::from_str(r#"
+            listen = "127.0.0.1:0"
+            secret = "hunter2"
+        "#).unwrap();
+        let listener = Listener::bind(config).await.unwrap();
+        let url = Url::parse(&format!("http://{}{PATH}", listener.local_addr().unwrap())).unwrap();
+        let (tx, mut rx) = mpsc::channel(8);
+        tokio::spawn(listener.run(tx));
+        let http = http::ReqwestClient::shared(&http::Config::default()).unwrap();
+
+        let submit = |secret: &str, body: serde_json::Value| {
+            let request = Request::post(url.clone()).header("X-Webhook-Secret", secret).json(&body).unwrap();
+            let http = http.clone();
+            async move { http.send(request).await.unwrap().status }
+        };
+
+        let deal = serde_json::json!({
+            "title": "[GPU] RX 7800 XT 16GB",
+            "url": "https://shop.example.com/p/7800xt",
+            "price": "1,049.5",
+            "source": "shop",
+        });
+        assert_eq!(submit("hunter2", deal.clone()).await, 202);
+        let post = rx.recv().await.unwrap();
+        assert_eq!(post.id, "shop:https://shop.example.com/p/7800xt");
+        assert_eq!(post.title, "[GPU] RX 7800 XT 16GB $1049.50");
+        assert_eq!(post.subreddit, "shop");
+        assert_eq!(post.domain, "shop.example.com");
+        assert_eq!(post.source_kind, Kind::Webhook);
+
+        let fullname = serde_json::json!({ "title": "[GPU] RX 7600 $249", "url": "https://shop.example.com/p/7600", "id": "t3_abc123" });
+        assert_eq!(submit("hunter2", fullname).await, 202);
+        let post = rx.recv().await.unwrap();
+        assert_eq!(post.id, "webhook:t3_abc123");
+        assert_eq!(post.source_kind, Kind::Webhook);
+
+        assert_eq!(submit("hunter3", deal).await, 401);
+        assert_eq!(submit("hunter2", serde_json::json!({ "title": "No url" })).await, 400);
+        assert_eq!(submit("hunter2", serde_json::json!({ "title": "Bad price", "url": "https://a.example", "price": "free" })).await, 400);
+
+        let wrong_path = http.send(Request::get(url.join("/").unwrap())).await.unwrap();
+        assert_eq!(wrong_path.status, 404);
+        assert!(rx.try_recv().is_err());
+    }
+}